@@ -0,0 +1,51 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use game_api::broadphase::BulletGrid;
+use game_api::pattern::{BulletTemplate, PatternRng};
+use game_api::{CollideType, GamePos, SimpleEnemyBullet, GAME_MAX_X, GAME_MAX_Y, GAME_MIN_X, GAME_MIN_Y};
+
+fn bullets(count: usize) -> Vec<SimpleEnemyBullet> {
+    let template = BulletTemplate::new(0, CollideType::Circle { radius: 6.0, radius_2: 36.0 }, 2.0);
+    let mut rng = PatternRng::new(42);
+    (0..count).map(|_| {
+        let pos = (rng.range(GAME_MIN_X, GAME_MAX_X), rng.range(GAME_MIN_Y, GAME_MAX_Y), 0.0).into();
+        template.spawn(pos, rng.range(0.0, 360.0))
+    }).collect()
+}
+
+/// The player and 50 player bullets against the enemy bullets
+fn targets() -> Vec<(CollideType, GamePos)> {
+    let mut rng = PatternRng::new(7);
+    let mut targets = vec![(CollideType::Circle { radius: 3.0, radius_2: 9.0 }, (0.0, -400.0, 0.0).into())];
+    targets.extend((0..50).map(|_| (CollideType::Rect { half_width: 4.0, half_height: 12.0 },
+                                    (rng.range(-200.0, 200.0), rng.range(-400.0, GAME_MAX_Y), 0.0).into())));
+    targets
+}
+
+fn naive(bullets: &[SimpleEnemyBullet], targets: &[(CollideType, GamePos)]) -> usize {
+    targets.iter().map(|(collide, pos)| {
+        bullets.iter().filter(|b| b.collide.is_collide_with(&b.pos, collide, pos)).count()
+    }).sum()
+}
+
+fn grid(grid: &mut BulletGrid, bullets: &[SimpleEnemyBullet], targets: &[(CollideType, GamePos)], out: &mut Vec<usize>) -> usize {
+    grid.rebuild(bullets);
+    targets.iter().map(|(collide, pos)| {
+        grid.collide_with(bullets, collide, pos, out);
+        out.len()
+    }).sum()
+}
+
+fn bench(c: &mut Criterion) {
+    let targets = targets();
+    for count in [1000, 5000] {
+        let bullets = bullets(count);
+        let mut g = BulletGrid::default();
+        let mut out = Vec::new();
+        assert_eq!(naive(&bullets, &targets), grid(&mut g, &bullets, &targets, &mut out));
+        c.bench_function(&format!("naive {}", count), |b| b.iter(|| naive(black_box(&bullets), &targets)));
+        c.bench_function(&format!("grid {}", count), |b| b.iter(|| grid(&mut g, black_box(&bullets), &targets, &mut out)));
+    }
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
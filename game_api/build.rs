@@ -13,18 +13,16 @@ fn main() {
             Ok(toolchain_dir) => {
                 let name = toolchain_dir.file_name();
                 let lib_loc = toolchain_dir.path().join("lib").join("rustlib")
-                    .join(format!("{}", name.to_string_lossy().replace("stable", "").replace("nightly", "").trim_start_matches("-")))
+                    .join(name.to_string_lossy().replace("stable", "").replace("nightly", "").trim_start_matches("-"))
                     .join("lib");
                 // println!("cargo:rustc-link-search={}", lib_loc.to_string_lossy());
-                for entry in lib_loc.read_dir().expect("Failed to read lib dir") {
-                    if let Ok(entry) = entry {
-                        let file_os_name = entry.file_name();
-                        let file_name = file_os_name.to_string_lossy();
-                        let out_file = out_path.join(&file_os_name);
-                        if file_name.starts_with("std") && !file_name.ends_with(".lib") && !file_name.ends_with(".pdb") {
-                            std::fs::copy(entry.path(), out_file).expect("Failed to copy dyn lib");
-                            // println!("cargo:rustc-link-lib=static={}", &file_name[..file_name.len() - 4]);
-                        }
+                for entry in lib_loc.read_dir().expect("Failed to read lib dir").flatten() {
+                    let file_os_name = entry.file_name();
+                    let file_name = file_os_name.to_string_lossy();
+                    let out_file = out_path.join(&file_os_name);
+                    if file_name.starts_with("std") && !file_name.ends_with(".lib") && !file_name.ends_with(".pdb") {
+                        std::fs::copy(entry.path(), out_file).expect("Failed to copy dyn lib");
+                        // println!("cargo:rustc-link-lib=static={}", &file_name[..file_name.len() - 4]);
                     }
                 }
             }
@@ -0,0 +1,111 @@
+//! The uniform grid over the play field to find the bullets near something
+//!
+//! The bullets out of the field are put in the cells on the edge
+//!
+
+use std::ops::RangeInclusive;
+
+use crate::{CollideType, GAME_MAX_X, GAME_MAX_Y, GAME_MIN_X, GAME_MIN_Y, GamePos, SimpleEnemyBullet};
+
+pub struct BulletGrid {
+    cell_size: f32,
+    cols: usize,
+    rows: usize,
+    /// The bullet indices in every cell, row by row from the left bottom
+    cells: Vec<Vec<usize>>,
+}
+
+impl BulletGrid {
+    /// The grid with the square cells of the size, None if the size is not positive and finite
+    pub fn new(cell_size: f32) -> Option<Self> {
+        if cell_size <= 0.0 || !cell_size.is_finite() {
+            return None;
+        }
+        let cols = ((GAME_MAX_X - GAME_MIN_X) / cell_size).ceil().max(1.0) as usize;
+        let rows = ((GAME_MAX_Y - GAME_MIN_Y) / cell_size).ceil().max(1.0) as usize;
+        Some(Self {
+            cell_size,
+            cols,
+            rows,
+            cells: vec![Vec::new(); cols * rows],
+        })
+    }
+
+    /// Remove all bullets and keep the storage of the cells
+    pub fn clear(&mut self) {
+        self.cells.iter_mut().for_each(Vec::clear);
+    }
+
+    fn col(&self, x: f32) -> usize {
+        (((x - GAME_MIN_X) / self.cell_size).floor().max(0.0) as usize).min(self.cols - 1)
+    }
+
+    fn row(&self, y: f32) -> usize {
+        (((y - GAME_MIN_Y) / self.cell_size).floor().max(0.0) as usize).min(self.rows - 1)
+    }
+
+    /// The columns and rows of the cells overlapped by the square of `extent` around the position
+    fn cell_bounds(&self, pos: &GamePos, extent: f32) -> (RangeInclusive<usize>, RangeInclusive<usize>) {
+        (self.col(pos.x - extent)..=self.col(pos.x + extent), self.row(pos.y - extent)..=self.row(pos.y + extent))
+    }
+
+    /// The cells overlapped by the square of `extent` around the position
+    fn cell_range(&self, pos: &GamePos, extent: f32) -> impl Iterator<Item=usize> + '_ {
+        let (cols, rows) = self.cell_bounds(pos, extent);
+        rows.flat_map(move |row| cols.clone().map(move |col| row * self.cols + col))
+    }
+
+    /// Insert the index of something within `extent` around the position
+    pub fn insert(&mut self, index: usize, pos: &GamePos, extent: f32) {
+        let (cols, rows) = self.cell_bounds(pos, extent);
+        for row in rows {
+            for col in cols.clone() {
+                self.cells[row * self.cols + col].push(index);
+            }
+        }
+    }
+
+    /// Clear and insert all bullets by their indices, called each tick after the bullets moved
+    pub fn rebuild(&mut self, bullets: &[SimpleEnemyBullet]) {
+        self.clear();
+        for (index, bullet) in bullets.iter().enumerate() {
+            self.insert(index, &bullet.pos, bullet.collide.get_bounding_radius());
+        }
+    }
+
+    /// Get the indices of the candidates within `extent` around the position, sorted without duplicates
+    pub fn query(&self, pos: &GamePos, extent: f32, out: &mut Vec<usize>) {
+        out.clear();
+        for cell in self.cell_range(pos, extent) {
+            out.extend_from_slice(&self.cells[cell]);
+        }
+        out.sort_unstable();
+        out.dedup();
+    }
+
+    /// Get the indices of the bullets colliding with the shape, the grid must be rebuilt from the same bullets
+    pub fn collide_with(&self, bullets: &[SimpleEnemyBullet], collide: &CollideType, pos: &GamePos, out: &mut Vec<usize>) {
+        self.query(pos, collide.get_bounding_radius(), out);
+        out.retain(|i| bullets[*i].collide.is_collide_with(&bullets[*i].pos, collide, pos));
+    }
+}
+
+impl Default for BulletGrid {
+    fn default() -> Self {
+        Self::new(64.0).expect("The cell size is positive")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_cell_size() {
+        for cell_size in [0.0, -1.0, f32::NAN, f32::INFINITY] {
+            assert!(BulletGrid::new(cell_size).is_none());
+        }
+        let grid = BulletGrid::new(2000.0).unwrap();
+        assert_eq!((grid.cols, grid.rows), (1, 1));
+    }
+}
@@ -0,0 +1,337 @@
+//! The enemies and bosses with the phases
+//!
+//! Every phase has its own hp bar, time limit and bullet pattern.
+//! The spell card is captured if it is beaten before timeout without the player dying or bombing
+//!
+
+use crate::pool::BulletPool;
+use crate::{CollideType, GamePos};
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PhaseKind {
+    NonSpell,
+    SpellCard,
+}
+
+/// What the pattern of the phase can see each tick
+#[derive(Debug, Copy, Clone)]
+pub struct PhaseState {
+    pub pos: GamePos,
+    /// The player position
+    pub target: GamePos,
+    /// The ticks since the phase started
+    pub tick: u32,
+}
+
+pub type PhasePattern = Box<dyn FnMut(&PhaseState, &mut BulletPool)>;
+
+pub struct Phase {
+    pub kind: PhaseKind,
+    pub name: String,
+    pub hp: f32,
+    /// The ticks before timeout, `u32::MAX` for no limit
+    pub time: u32,
+    /// The bonus when the spell card is captured
+    pub bonus: u64,
+    pattern: Option<PhasePattern>,
+}
+
+impl Phase {
+    pub fn new(kind: PhaseKind, name: String, hp: f32, time: u32) -> Self {
+        Self {
+            kind,
+            name,
+            hp,
+            time,
+            bonus: 0,
+            pattern: None,
+        }
+    }
+
+    pub fn with_bonus(mut self, bonus: u64) -> Self {
+        self.bonus = bonus;
+        self
+    }
+
+    /// The pattern is called every tick to spawn the bullets
+    pub fn with_pattern(mut self, pattern: impl FnMut(&PhaseState, &mut BulletPool) + 'static) -> Self {
+        self.pattern = Some(Box::new(pattern));
+        self
+    }
+}
+
+/// The points moved through linearly, each point is reached in its ticks
+#[derive(Debug, Clone, Default)]
+pub struct MovePath {
+    pub points: Vec<(GamePos, u32)>,
+    /// Start again from the first point after the last one, otherwise stay at the last point
+    pub looping: bool,
+    index: usize,
+    tick: u32,
+    from: Option<GamePos>,
+}
+
+impl MovePath {
+    pub fn new(points: Vec<(GamePos, u32)>, looping: bool) -> Self {
+        Self {
+            points,
+            looping,
+            index: 0,
+            tick: 0,
+            from: None,
+        }
+    }
+
+    /// Get the next position from the current one
+    pub fn tick(&mut self, pos: &GamePos) -> GamePos {
+        let (to, ticks) = match self.points.get(self.index) {
+            Some(point) => *point,
+            None => return *pos,
+        };
+        let from = *self.from.get_or_insert(*pos);
+        self.tick += 1;
+        let t = if ticks == 0 { 1.0 } else { (self.tick as f32 / ticks as f32).min(1.0) };
+        if t >= 1.0 {
+            self.index += 1;
+            self.tick = 0;
+            self.from = None;
+            if self.looping && self.index >= self.points.len() {
+                self.index = 0;
+            }
+        }
+        (from.x + (to.x - from.x) * t, from.y + (to.y - from.y) * t, from.z + (to.z - from.z) * t).into()
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EnemyEvent {
+    None,
+    /// The phase ended and the next one started
+    PhaseChanged {
+        ended: usize,
+        timed_out: bool,
+        /// The capture bonus, zero if not a captured spell card
+        bonus: u64,
+    },
+    /// The last phase ended
+    Defeated {
+        timed_out: bool,
+        bonus: u64,
+    },
+}
+
+pub struct Enemy {
+    pub pos: GamePos,
+    pub collide: CollideType,
+    /// The hp left of the current phase
+    pub hp: f32,
+    pub path: MovePath,
+    phases: Vec<Phase>,
+    phase: usize,
+    phase_tick: u32,
+    capture_failed: bool,
+}
+
+impl Enemy {
+    /// The enemy with the phases in order, at least one phase is needed
+    pub fn new(pos: GamePos, collide: CollideType, path: MovePath, phases: Vec<Phase>) -> Option<Self> {
+        let hp = phases.first()?.hp;
+        Some(Self {
+            pos,
+            collide,
+            hp,
+            path,
+            phases,
+            phase: 0,
+            phase_tick: 0,
+            capture_failed: false,
+        })
+    }
+
+    /// The enemy with one non-spell phase and no time limit
+    pub fn simple(pos: GamePos, collide: CollideType, hp: f32, path: MovePath, pattern: impl FnMut(&PhaseState, &mut BulletPool) + 'static) -> Self {
+        let phase = Phase::new(PhaseKind::NonSpell, String::new(), hp, u32::MAX).with_pattern(pattern);
+        Self::new(pos, collide, path, vec![phase]).expect("One phase is given")
+    }
+
+    pub fn get_hitbox(&self) -> (CollideType, GamePos) {
+        (self.collide, self.pos)
+    }
+
+    /// The index of the current phase, equal to the phase count after defeated
+    pub fn get_phase_index(&self) -> usize {
+        self.phase
+    }
+
+    pub fn get_phase(&self) -> Option<&Phase> {
+        self.phases.get(self.phase)
+    }
+
+    pub fn get_phases(&self) -> &[Phase] {
+        &self.phases
+    }
+
+    pub fn is_defeated(&self) -> bool {
+        self.phase >= self.phases.len()
+    }
+
+    /// The hp left of the current phase in [0, 1]
+    pub fn get_hp_ratio(&self) -> f32 {
+        self.get_phase().map_or(0.0, |phase| if phase.hp > 0.0 { (self.hp / phase.hp).clamp(0.0, 1.0) } else { 0.0 })
+    }
+
+    /// The ticks before the current phase times out
+    pub fn get_time_left(&self) -> u32 {
+        self.get_phase().map_or(0, |phase| phase.time.saturating_sub(self.phase_tick))
+    }
+
+    /// The current spell card cannot be captured, called when the player dies or bombs
+    pub fn fail_capture(&mut self) {
+        self.capture_failed = true;
+    }
+
+    pub fn damage(&mut self, damage: f32) {
+        if !self.is_defeated() {
+            self.hp -= damage;
+        }
+    }
+
+    /// Move, run the pattern of the phase and end the phase beaten or timed out
+    pub fn tick(&mut self, target: &GamePos, bullets: &mut BulletPool) -> EnemyEvent {
+        if self.is_defeated() {
+            return EnemyEvent::None;
+        }
+        self.pos = self.path.tick(&self.pos);
+        let state = PhaseState {
+            pos: self.pos,
+            target: *target,
+            tick: self.phase_tick,
+        };
+        let phase = &mut self.phases[self.phase];
+        if let Some(pattern) = phase.pattern.as_mut() {
+            pattern(&state, bullets);
+        }
+        self.phase_tick = self.phase_tick.saturating_add(1);
+
+        let timed_out = phase.time != u32::MAX && self.phase_tick >= phase.time;
+        if self.hp > 0.0 && !timed_out {
+            return EnemyEvent::None;
+        }
+        let captured = phase.kind == PhaseKind::SpellCard && !timed_out && !self.capture_failed;
+        let bonus = if captured { phase.bonus } else { 0 };
+        let ended = self.phase;
+        self.phase += 1;
+        self.phase_tick = 0;
+        self.capture_failed = false;
+        match self.phases.get(self.phase) {
+            Some(next) => {
+                self.hp = next.hp;
+                EnemyEvent::PhaseChanged { ended, timed_out, bonus }
+            }
+            None => {
+                self.hp = 0.0;
+                EnemyEvent::Defeated { timed_out, bonus }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use super::*;
+
+    fn pos(x: f32, y: f32) -> GamePos {
+        (x, y, 0.0).into()
+    }
+
+    fn boss(phases: Vec<Phase>) -> Enemy {
+        Enemy::new(pos(0.0, 0.0), CollideType::Circle { radius: 10.0, radius_2: 100.0 }, MovePath::default(), phases).unwrap()
+    }
+
+    fn spell(hp: f32, time: u32, bonus: u64) -> Phase {
+        Phase::new(PhaseKind::SpellCard, "spell".into(), hp, time).with_bonus(bonus)
+    }
+
+    #[test]
+    fn spell_captured() {
+        let mut enemy = boss(vec![spell(10.0, 100, 500), spell(10.0, 100, 800)]);
+        let mut bullets = BulletPool::default();
+        assert_eq!(enemy.tick(&pos(0.0, 0.0), &mut bullets), EnemyEvent::None);
+        enemy.damage(10.0);
+        assert_eq!(enemy.tick(&pos(0.0, 0.0), &mut bullets), EnemyEvent::PhaseChanged { ended: 0, timed_out: false, bonus: 500 });
+        assert_eq!((enemy.get_phase_index(), enemy.hp, enemy.get_time_left()), (1, 10.0, 100));
+    }
+
+    #[test]
+    fn spell_timed_out() {
+        let mut enemy = boss(vec![spell(10.0, 3, 500), spell(10.0, 100, 800)]);
+        let mut bullets = BulletPool::default();
+        assert_eq!(enemy.tick(&pos(0.0, 0.0), &mut bullets), EnemyEvent::None);
+        assert_eq!(enemy.tick(&pos(0.0, 0.0), &mut bullets), EnemyEvent::None);
+        assert_eq!(enemy.tick(&pos(0.0, 0.0), &mut bullets), EnemyEvent::PhaseChanged { ended: 0, timed_out: true, bonus: 0 });
+    }
+
+    #[test]
+    fn capture_failed() {
+        let mut enemy = boss(vec![spell(10.0, 100, 500), spell(10.0, 100, 800)]);
+        let mut bullets = BulletPool::default();
+        enemy.fail_capture();
+        enemy.damage(10.0);
+        assert_eq!(enemy.tick(&pos(0.0, 0.0), &mut bullets), EnemyEvent::PhaseChanged { ended: 0, timed_out: false, bonus: 0 });
+        // the next spell card can be captured again
+        enemy.damage(10.0);
+        assert_eq!(enemy.tick(&pos(0.0, 0.0), &mut bullets), EnemyEvent::Defeated { timed_out: false, bonus: 800 });
+    }
+
+    #[test]
+    fn defeated() {
+        let fired = Rc::new(Cell::new(0));
+        let counter = fired.clone();
+        let phase = Phase::new(PhaseKind::NonSpell, String::new(), 5.0, u32::MAX).with_pattern(move |_, _| counter.set(counter.get() + 1));
+        let mut enemy = boss(vec![phase]);
+        let mut bullets = BulletPool::default();
+        enemy.damage(5.0);
+        assert_eq!(enemy.tick(&pos(0.0, 0.0), &mut bullets), EnemyEvent::Defeated { timed_out: false, bonus: 0 });
+        assert!(enemy.is_defeated());
+        assert_eq!(enemy.get_hp_ratio(), 0.0);
+
+        enemy.damage(5.0);
+        assert_eq!(enemy.hp, 0.0);
+        assert_eq!(enemy.tick(&pos(0.0, 0.0), &mut bullets), EnemyEvent::None);
+        assert_eq!(fired.get(), 1);
+    }
+
+    #[test]
+    fn no_phase() {
+        assert!(Enemy::new(pos(0.0, 0.0), CollideType::Circle { radius: 1.0, radius_2: 1.0 }, MovePath::default(), Vec::new()).is_none());
+    }
+
+    #[test]
+    fn path_zero_ticks() {
+        let mut path = MovePath::new(vec![(pos(10.0, 20.0), 0), (pos(30.0, 20.0), 2)], false);
+        let start = path.tick(&pos(0.0, 0.0));
+        assert_eq!(start, pos(10.0, 20.0));
+        let half = path.tick(&start);
+        assert_eq!(half, pos(20.0, 20.0));
+        let end = path.tick(&half);
+        assert_eq!(end, pos(30.0, 20.0));
+        assert_eq!(path.tick(&end), end);
+    }
+
+    #[test]
+    fn path_looping() {
+        let mut path = MovePath::new(vec![(pos(10.0, 0.0), 1), (pos(0.0, 10.0), 1)], true);
+        let mut at = pos(0.0, 0.0);
+        let mut visited = Vec::new();
+        for _ in 0..5 {
+            at = path.tick(&at);
+            visited.push(at);
+        }
+        assert_eq!(visited, vec![pos(10.0, 0.0), pos(0.0, 10.0), pos(10.0, 0.0), pos(0.0, 10.0), pos(10.0, 0.0)]);
+    }
+}
@@ -11,24 +11,50 @@ use std::convert::TryFrom;
 use std::io::{Error, ErrorKind};
 
 use crate::{PLAYER_Z, TexHandle};
+use crate::geometry::{Obb, segment_segment_distance_2, Vec2};
 
 pub const GAME_MAX_X: f32 = 800.0;
 pub const GAME_MIN_X: f32 = -800.0;
 pub const GAME_MAX_Y: f32 = 450.0;
 pub const GAME_MIN_Y: f32 = -450.0;
 
+/// Where the player starts and respawns
+pub const PLAYER_SPAWN: (f32, f32, f32) = (0.0, -400.0, PLAYER_Z);
+
 #[repr(C)]
 pub struct Player {
     pub pos: GamePos,
     pub move_speed: f32,
     pub walk_speed: f32,
     pub radius: f32,
+    /// The bullets within this radius are grazed
+    pub graze_radius: f32,
+    /// The count of the bullets grazed
+    pub graze: u32,
+    /// How far the position is kept from the field edges
+    pub border: f32,
     ///
     /// zero is no death
     /// &gt; 0 is dying
     /// &lt; 0 is died and ticks for death
     ///
+    /// See [`Player::tick_death`]
+    ///
     pub death: isize,
+    /// The ticks to bomb after hit to cancel the death, zero for no deathbomb
+    pub deathbomb_ticks: isize,
+    /// The ticks from died to respawn
+    pub death_ticks: isize,
+    /// The ticks not able to be hit after respawn or deathbomb
+    pub invulnerable_ticks: u32,
+    /// The ticks left not able to be hit
+    pub invulnerable: u32,
+    /// The lives left including the current one
+    pub lives: u32,
+    pub bombs: u32,
+    pub power: u32,
+    pub max_power: u32,
+    pub score: u64,
     pub tex: usize,
     pub shoot_cooldown: u8,
     pub walking: bool,
@@ -42,22 +68,126 @@ pub struct GamePos {
     pub z: f32,
 }
 
-impl Into<GamePos> for (f32, f32, f32) {
-    fn into(self) -> GamePos {
-        GamePos {
-            x: self.0,
-            y: self.1,
-            z: self.2,
+impl From<(f32, f32, f32)> for GamePos {
+    fn from((x, y, z): (f32, f32, f32)) -> Self {
+        Self {
+            x,
+            y,
+            z,
         }
     }
 }
 
+/// The collide shapes, all positioned by the position of the owner
+///
+/// The byte codes and args in the decoder:
+///
+/// 10 circle: radius
+///
+/// 11 rect: half width, half height
+///
+/// 12 oriented rect: half width, half height, angle
+///
+/// 13 capsule: half length, radius, angle
+///
+/// 14 laser: length, radius, angle
+///
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub enum CollideType {
     Circle {
         radius: f32,
         radius_2: f32,
+    },
+    /// The axis-aligned rectangle centered at the position
+    Rect {
+        half_width: f32,
+        half_height: f32,
+    },
+    /// The rectangle centered at the position, the width is along the facing
+    OrientedRect {
+        half_width: f32,
+        half_height: f32,
+        facing_x: f32,
+        facing_y: f32,
+    },
+    /// The segment centered at the position along the facing and the radius around it
+    Capsule {
+        half_length: f32,
+        radius: f32,
+        facing_x: f32,
+        facing_y: f32,
+    },
+    /// The segment from the position along the facing and the radius around it
+    Laser {
+        length: f32,
+        radius: f32,
+        facing_x: f32,
+        facing_y: f32,
+    },
+}
+
+enum Shape {
+    Segment {
+        a: Vec2,
+        b: Vec2,
+        radius: f32,
+    },
+    Box(Obb),
+}
+
+/// Zero distance is overlapping even with zero radius
+fn is_within(distance_2: f32, radius: f32) -> bool {
+    distance_2 <= 0.0 || distance_2 < radius * radius
+}
+
+fn facing(angle: f32) -> (f32, f32) {
+    let (sin, cos) = (angle * std::f32::consts::PI / 180.0).sin_cos();
+    (cos, sin)
+}
+
+impl CollideType {
+    fn shape(&self, pos: &GamePos) -> Shape {
+        let c = (pos.x, pos.y);
+        match *self {
+            Self::Circle { radius, .. } => Shape::Segment { a: c, b: c, radius },
+            Self::Rect { half_width, half_height } => Shape::Box(Obb { center: c, axis: (1.0, 0.0), half_width, half_height }),
+            Self::OrientedRect { half_width, half_height, facing_x, facing_y } => Shape::Box(Obb { center: c, axis: (facing_x, facing_y), half_width, half_height }),
+            Self::Capsule { half_length, radius, facing_x, facing_y } => {
+                let (dx, dy) = (facing_x * half_length, facing_y * half_length);
+                Shape::Segment { a: (c.0 - dx, c.1 - dy), b: (c.0 + dx, c.1 + dy), radius }
+            }
+            Self::Laser { length, radius, facing_x, facing_y } => Shape::Segment { a: c, b: (c.0 + facing_x * length, c.1 + facing_y * length), radius },
+        }
+    }
+
+    /// Whether the segment a-b touches the shape at the position
+    fn touches_segment(&self, pos: &GamePos, a: Vec2, b: Vec2) -> bool {
+        match self.shape(pos) {
+            Shape::Segment { a: c, b: d, radius } => is_within(segment_segment_distance_2(a, b, c, d), radius),
+            Shape::Box(obb) => obb.segment_distance_2(a, b) <= 0.0,
+        }
+    }
+
+    /// The radius of the circle around the position containing the shape
+    pub extern "C" fn get_bounding_radius(self) -> f32 {
+        match self {
+            Self::Circle { radius, .. } => radius,
+            Self::Rect { half_width, half_height } | Self::OrientedRect { half_width, half_height, .. } => half_width.hypot(half_height),
+            Self::Capsule { half_length, radius, .. } => half_length + radius,
+            Self::Laser { length, radius, .. } => length + radius,
+        }
+    }
+
+    /// Face the oriented shapes to the angle, the others are unchanged
+    pub extern "C" fn with_angle(self, angle: f32) -> Self {
+        let (facing_x, facing_y) = facing(angle);
+        match self {
+            Self::Circle { .. } | Self::Rect { .. } => self,
+            Self::OrientedRect { half_width, half_height, .. } => Self::OrientedRect { half_width, half_height, facing_x, facing_y },
+            Self::Capsule { half_length, radius, .. } => Self::Capsule { half_length, radius, facing_x, facing_y },
+            Self::Laser { length, radius, .. } => Self::Laser { length, radius, facing_x, facing_y },
+        }
     }
 }
 
@@ -72,6 +202,7 @@ impl CollideType {
                 let y_distance = me.y - other.y;
                 x_distance * x_distance + y_distance * y_distance < r_2
             }
+            _ => self.touches_segment(me, (other.x, other.y), (other.x, other.y)),
         }
     }
 
@@ -98,27 +229,40 @@ impl CollideType {
                     }
                 }
             }
+            // the shape moved up to `up` contains the point if the shape touches the point moved down
+            _ => self.touches_segment(point, (me.x, me.y - up), (me.x, me.y)),
         }
     }
 
     pub extern "C" fn is_collide_with(self, me: &GamePos, other_collide: &CollideType, other: &GamePos) -> bool {
         match self {
             Self::Circle {
-                radius: _,
-                radius_2: r_2
+                radius: r,
+                radius_2: _
             } => {
                 match other_collide {
                     Self::Circle {
-                        radius: _,
-                        radius_2: o_r_2
+                        radius: o_r,
+                        radius_2: _
                     } => {
                         let center_x_distance = me.x - other.x;
                         let center_y_distance = me.y - other.y;
                         let center_distance = center_x_distance * center_x_distance + center_y_distance * center_y_distance;
-                        center_distance < r_2 + o_r_2
+                        let radius_sum = r + o_r;
+                        center_distance < radius_sum * radius_sum
                     }
+                    _ => self.is_shape_collide_with(me, other_collide, other),
                 }
             }
+            _ => self.is_shape_collide_with(me, other_collide, other),
+        }
+    }
+
+    fn is_shape_collide_with(self, me: &GamePos, other_collide: &CollideType, other: &GamePos) -> bool {
+        match (self.shape(me), other_collide.shape(other)) {
+            (Shape::Segment { a, b, radius }, Shape::Segment { a: c, b: d, radius: o_radius }) => is_within(segment_segment_distance_2(a, b, c, d), radius + o_radius),
+            (Shape::Segment { a, b, radius }, Shape::Box(obb)) | (Shape::Box(obb), Shape::Segment { a, b, radius }) => is_within(obb.segment_distance_2(a, b), radius),
+            (Shape::Box(mine), Shape::Box(others)) => mine.intersects(&others),
         }
     }
 }
@@ -127,19 +271,30 @@ impl TryFrom<(u8, Vec<f32>)> for CollideType {
     type Error = Error;
 
     fn try_from((value, args): (u8, Vec<f32>)) -> Result<Self, Self::Error> {
+        let arg_count = CollideType::get_arg_count(value);
+        if arg_count != 0 && args.len() < arg_count {
+            return Err(Error::new(ErrorKind::InvalidData, format!("CollideType {} needs {} args but got {}", value, arg_count, args.len())));
+        }
         match value {
             10 => Ok(CollideType::Circle { radius: args[0], radius_2: args[0] * args[0] }),
+            11 => Ok(CollideType::Rect { half_width: args[0], half_height: args[1] }),
+            12 => Ok(CollideType::OrientedRect { half_width: args[0], half_height: args[1], facing_x: 1.0, facing_y: 0.0 }.with_angle(args[2])),
+            13 => Ok(CollideType::Capsule { half_length: args[0], radius: args[1], facing_x: 1.0, facing_y: 0.0 }.with_angle(args[2])),
+            14 => Ok(CollideType::Laser { length: args[0], radius: args[1], facing_x: 1.0, facing_y: 0.0 }.with_angle(args[2])),
             _ => Err(Error::new(ErrorKind::InvalidData, "No such value for CollideType: ".to_owned() + &*value.to_string()))
         }
     }
 }
 
 impl CollideType {
+    /// The count of the f32 args following the byte, zero if the byte is not a collide type
     //noinspection RsSelfConvention
     pub extern "C" fn get_arg_count(byte: u8) -> usize {
         match byte {
             10 => 1,
-            _ => panic!("Not collide byte: {}", byte)
+            11 => 2,
+            12..=14 => 3,
+            _ => 0
         }
     }
 }
@@ -153,13 +308,25 @@ impl Default for Player {
 impl Player {
     pub extern "C" fn new(move_speed: f32, walk_speed: f32) -> Self {
         Self {
-            pos: (0.0, -400.0, PLAYER_Z).into(),
+            pos: PLAYER_SPAWN.into(),
             move_speed,
             walk_speed,
             walking: false,
             radius: 5.0,
+            graze_radius: 24.0,
+            graze: 0,
+            border: 16.0,
             shoot_cooldown: 0,
             death: 0,
+            deathbomb_ticks: 8,
+            death_ticks: 60,
+            invulnerable_ticks: 180,
+            invulnerable: 0,
+            lives: 3,
+            bombs: 3,
+            power: 0,
+            max_power: 400,
+            score: 0,
             tex: 0,
         }
     }
@@ -171,6 +338,13 @@ pub struct PlayerBullet {
     pub pos: GamePos,
     pub tex: TexHandle,
     pub damage: f32,
+    /// The velocity per tick
+    pub vx: f32,
+    pub vy: f32,
+    /// The radius of the circle hitbox
+    pub radius: f32,
+    /// The max degrees turned to the target per tick, zero for not homing
+    pub homing: f32,
 }
 
 
@@ -206,6 +380,7 @@ impl Rotation {
 pub struct SimpleEnemyBullet {
     pub pos: GamePos,
     pub tex: TexHandle,
+    /// Facing the moving direction
     pub collide: CollideType,
     pub speed: f32,
     pub rotation: Rotation,
@@ -213,6 +388,8 @@ pub struct SimpleEnemyBullet {
     pub a_delta: f32,
     pub w: f32,
     pub w_delta: f32,
+    /// Each bullet is grazed once
+    pub grazed: bool,
 }
 
 impl SimpleEnemyBullet {
@@ -221,7 +398,7 @@ impl SimpleEnemyBullet {
         Self {
             pos,
             tex,
-            collide,
+            collide: collide.with_angle(angle),
             speed,
             rotation: Rotation {
                 facing_x: cos,
@@ -233,6 +410,7 @@ impl SimpleEnemyBullet {
             a_delta: 0.0,
             w: 0.0,
             w_delta: 0.0,
+            grazed: false,
         }
     }
 
@@ -242,6 +420,9 @@ impl SimpleEnemyBullet {
         self.speed += self.a;
         self.a += self.a_delta;
         self.rotation.add_angle(self.w);
+        if self.w != 0.0 {
+            self.collide = self.collide.with_angle(self.rotation.angle);
+        }
         self.w += self.w_delta;
     }
 }
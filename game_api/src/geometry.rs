@@ -0,0 +1,95 @@
+//! The 2d geometry for the collide shapes
+//!
+
+pub(crate) type Vec2 = (f32, f32);
+
+fn sub(a: Vec2, b: Vec2) -> Vec2 {
+    (a.0 - b.0, a.1 - b.1)
+}
+
+fn dot(a: Vec2, b: Vec2) -> f32 {
+    a.0 * b.0 + a.1 * b.1
+}
+
+fn cross(a: Vec2, b: Vec2) -> f32 {
+    a.0 * b.1 - a.1 * b.0
+}
+
+/// The squared distance from the point to the segment a-b
+pub(crate) fn point_segment_distance_2(p: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let ab = sub(b, a);
+    let ap = sub(p, a);
+    let len_2 = dot(ab, ab);
+    let t = if len_2 > 0.0 { (dot(ap, ab) / len_2).clamp(0.0, 1.0) } else { 0.0 };
+    let d = sub(ap, (ab.0 * t, ab.1 * t));
+    dot(d, d)
+}
+
+/// The squared distance between the segments a-b and c-d, zero if they cross
+pub(crate) fn segment_segment_distance_2(a: Vec2, b: Vec2, c: Vec2, d: Vec2) -> f32 {
+    let d1 = cross(sub(b, a), sub(c, a));
+    let d2 = cross(sub(b, a), sub(d, a));
+    let d3 = cross(sub(d, c), sub(a, c));
+    let d4 = cross(sub(d, c), sub(b, c));
+    if d1 * d2 < 0.0 && d3 * d4 < 0.0 {
+        return 0.0;
+    }
+    // touching and collinear cases have one of the endpoints on the other segment
+    point_segment_distance_2(a, c, d)
+        .min(point_segment_distance_2(b, c, d))
+        .min(point_segment_distance_2(c, a, b))
+        .min(point_segment_distance_2(d, a, b))
+}
+
+/// The oriented box
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Obb {
+    pub center: Vec2,
+    /// The unit vector of the local x axis
+    pub axis: Vec2,
+    pub half_width: f32,
+    pub half_height: f32,
+}
+
+impl Obb {
+    fn local(&self, p: Vec2) -> Vec2 {
+        let d = sub(p, self.center);
+        (dot(d, self.axis), cross(self.axis, d))
+    }
+
+    pub fn contains(&self, p: Vec2) -> bool {
+        let (x, y) = self.local(p);
+        x.abs() < self.half_width && y.abs() < self.half_height
+    }
+
+    fn corners(&self) -> [Vec2; 4] {
+        let (ax, ay) = self.axis;
+        let (w, h) = (self.half_width, self.half_height);
+        let (cx, cy) = self.center;
+        let at = |x: f32, y: f32| (cx + ax * x - ay * y, cy + ay * x + ax * y);
+        [at(-w, -h), at(w, -h), at(w, h), at(-w, h)]
+    }
+
+    /// The squared distance from the segment to the box, zero if they overlap
+    pub fn segment_distance_2(&self, a: Vec2, b: Vec2) -> f32 {
+        if self.contains(a) || self.contains(b) {
+            return 0.0;
+        }
+        let corners = self.corners();
+        (0..4).map(|i| segment_segment_distance_2(a, b, corners[i], corners[(i + 1) % 4]))
+            .fold(f32::INFINITY, f32::min)
+    }
+
+    /// Separating axis test
+    pub fn intersects(&self, other: &Obb) -> bool {
+        let (mine, others) = (self.corners(), other.corners());
+        let axes = [self.axis, (-self.axis.1, self.axis.0), other.axis, (-other.axis.1, other.axis.0)];
+        axes.iter().all(|axis| {
+            let project = |corners: &[Vec2; 4]| corners.iter().map(|c| dot(*c, *axis))
+                .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), x| (min.min(x), max.max(x)));
+            let (a_min, a_max) = project(&mine);
+            let (b_min, b_max) = project(&others);
+            a_min < b_max && b_min < a_max
+        })
+    }
+}
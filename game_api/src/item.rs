@@ -0,0 +1,293 @@
+//! The items dropped by the enemies and collected by the player
+//!
+//! The items pop up and then fall. They fly to the player while focused nearby,
+//! and all items fly to the player once it is above the collect line
+//!
+
+use crate::pool::BulletPool;
+use crate::{GAME_MIN_Y, GamePos, Player, TexHandle};
+
+/// The upward speed when dropped
+pub const ITEM_POP_SPEED: f32 = 6.0;
+/// The speed change per tick
+pub const ITEM_GRAVITY: f32 = 0.25;
+/// The max falling speed
+pub const ITEM_MAX_FALL_SPEED: f32 = 4.0;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ItemKind {
+    Power,
+    BigPower,
+    Point,
+    Bomb,
+    Life,
+    /// Converted from the bullets, a little score
+    Cancel,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct Item {
+    pub kind: ItemKind,
+    pub pos: GamePos,
+    pub tex: TexHandle,
+    pub vx: f32,
+    pub vy: f32,
+    /// Flying to the player, it falls again only while the player is dying or died
+    pub attracted: bool,
+    /// Attracted by the collect line, the point item gives the full value
+    pub auto_collected: bool,
+}
+
+impl Item {
+    /// Drop the item with an upward pop
+    pub extern "C" fn new_dropped(kind: ItemKind, pos: GamePos, tex: TexHandle) -> Self {
+        Self {
+            kind,
+            pos,
+            tex,
+            vx: 0.0,
+            vy: ITEM_POP_SPEED,
+            attracted: false,
+            auto_collected: false,
+        }
+    }
+
+    /// Fly to the target by the speed if attracted, otherwise fall
+    pub extern "C" fn tick(&mut self, target: &GamePos, speed: f32) {
+        if self.attracted {
+            let (dx, dy) = (target.x - self.pos.x, target.y - self.pos.y);
+            let len = (dx * dx + dy * dy).sqrt();
+            let t = if len > speed { speed / len } else { 1.0 };
+            self.vx = dx * t;
+            self.vy = dy * t;
+        } else {
+            self.vx = 0.0;
+            self.vy = (self.vy - ITEM_GRAVITY).max(-ITEM_MAX_FALL_SPEED);
+        }
+        self.pos.x += self.vx;
+        self.pos.y += self.vy;
+    }
+}
+
+pub struct ItemField {
+    items: Vec<Item>,
+    /// All items are attracted while the player is above this y
+    pub collect_line: f32,
+    /// The items within this radius are attracted while focused
+    pub attract_radius: f32,
+    /// The items within this radius are collected
+    pub collect_radius: f32,
+    /// The speed of the attracted items per tick
+    pub attract_speed: f32,
+    /// The score of the point item collected above the line, it gives half at the bottom of the field
+    pub point_value: u64,
+    pub cancel_value: u64,
+    pub power_value: u32,
+    pub big_power_value: u32,
+}
+
+impl ItemField {
+    pub fn new(collect_line: f32) -> Self {
+        Self {
+            items: Vec::new(),
+            collect_line,
+            attract_radius: 100.0,
+            collect_radius: 24.0,
+            attract_speed: 12.0,
+            point_value: 10000,
+            cancel_value: 10,
+            power_value: 1,
+            big_power_value: 8,
+        }
+    }
+
+    pub fn spawn(&mut self, item: Item) {
+        self.items.push(item);
+    }
+
+    pub fn get_items(&self) -> &[Item] {
+        &self.items
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+
+    /// Turn all bullets to the cancel items flying to the player and clear the bullets, return the count converted
+    pub fn convert_bullets(&mut self, bullets: &mut BulletPool, tex: TexHandle) -> usize {
+        let count = bullets.len();
+        self.items.extend(bullets.get_bullets().iter().map(|bullet| Item {
+            attracted: true,
+            auto_collected: true,
+            ..Item::new_dropped(ItemKind::Cancel, bullet.pos, tex)
+        }));
+        bullets.clear();
+        count
+    }
+
+    /// The score of the point item collected at the height
+    pub fn get_point_value(&self, item: &Item, y: f32) -> u64 {
+        if item.auto_collected || y >= self.collect_line {
+            return self.point_value;
+        }
+        let t = ((y - GAME_MIN_Y) / (self.collect_line - GAME_MIN_Y)).clamp(0.0, 1.0);
+        (self.point_value as f32 * (0.5 + 0.5 * t)) as u64
+    }
+
+    /// Give the item to the player
+    pub fn collect(&self, player: &mut Player, item: &Item) {
+        match item.kind {
+            ItemKind::Power => player.power = (player.power + self.power_value).min(player.max_power),
+            ItemKind::BigPower => player.power = (player.power + self.big_power_value).min(player.max_power),
+            ItemKind::Point => player.score += self.get_point_value(item, player.pos.y),
+            ItemKind::Bomb => player.bombs += 1,
+            ItemKind::Life => player.lives += 1,
+            ItemKind::Cancel => player.score += self.cancel_value,
+        }
+    }
+
+    /// Attract, move and collect the items, and remove the ones fallen out of the field, return the count collected
+    ///
+    /// The items fall and are not collected while the player is dying or died
+    pub fn tick(&mut self, player: &mut Player) -> usize {
+        let alive = player.death == 0;
+        let above_line = alive && player.pos.y >= self.collect_line;
+        let attract_2 = self.attract_radius * self.attract_radius;
+        let collect_2 = self.collect_radius * self.collect_radius;
+        let mut collected = 0;
+        let mut items = std::mem::take(&mut self.items);
+        items.retain_mut(|item| {
+            if !alive {
+                item.attracted = false;
+            } else if above_line && !item.attracted {
+                item.attracted = true;
+                item.auto_collected = true;
+            }
+            let distance_2 = |item: &Item| (item.pos.x - player.pos.x).powi(2) + (item.pos.y - player.pos.y).powi(2);
+            if alive && player.walking && distance_2(item) <= attract_2 {
+                item.attracted = true;
+            }
+            item.tick(&player.pos, self.attract_speed);
+            if alive && distance_2(item) <= collect_2 {
+                self.collect(player, item);
+                collected += 1;
+                return false;
+            }
+            item.pos.y >= GAME_MIN_Y - self.collect_radius
+        });
+        self.items = items;
+        collected
+    }
+}
+
+impl Default for ItemField {
+    fn default() -> Self {
+        Self::new(200.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern::{ring, BulletTemplate};
+    use crate::CollideType;
+
+    fn pos(x: f32, y: f32) -> GamePos {
+        (x, y, 0.0).into()
+    }
+
+    fn player_at(x: f32, y: f32, walking: bool) -> Player {
+        Player { pos: pos(x, y), walking, ..Default::default() }
+    }
+
+    #[test]
+    fn pop_then_fall() {
+        let mut field = ItemField::default();
+        let mut player = player_at(0.0, -400.0, false);
+        field.spawn(Item::new_dropped(ItemKind::Point, pos(500.0, 0.0), 0));
+        field.tick(&mut player);
+        let item = field.get_items()[0];
+        assert_eq!((item.pos.y, item.vy), (ITEM_POP_SPEED - ITEM_GRAVITY, ITEM_POP_SPEED - ITEM_GRAVITY));
+        for _ in 0..100 {
+            field.tick(&mut player);
+        }
+        let item = field.get_items()[0];
+        assert_eq!((item.pos.x, item.vy), (500.0, -ITEM_MAX_FALL_SPEED));
+        for _ in 0..200 {
+            field.tick(&mut player);
+        }
+        assert!(field.get_items().is_empty());
+        assert_eq!(player.score, 0);
+    }
+
+    #[test]
+    fn attracted_while_focused() {
+        for walking in [false, true] {
+            let mut field = ItemField::default();
+            let mut player = player_at(0.0, -400.0, walking);
+            field.spawn(Item::new_dropped(ItemKind::Power, pos(80.0, -350.0), 0));
+            field.tick(&mut player);
+            assert_eq!(field.get_items()[0].attracted, walking);
+            assert!(!field.get_items()[0].auto_collected);
+        }
+    }
+
+    #[test]
+    fn collect_line() {
+        let mut field = ItemField::default();
+        let mut player = player_at(0.0, 300.0, false);
+        field.spawn(Item::new_dropped(ItemKind::Point, pos(-700.0, -300.0), 0));
+        field.tick(&mut player);
+        assert!(field.get_items()[0].attracted && field.get_items()[0].auto_collected);
+        let collected = (0..100).map(|_| field.tick(&mut player)).sum::<usize>();
+        assert_eq!(collected, 1);
+        assert_eq!(player.score, field.point_value);
+    }
+
+    #[test]
+    fn not_collected_while_dying() {
+        let mut field = ItemField::default();
+        let mut player = Player { death: 5, ..player_at(0.0, 300.0, true) };
+        field.spawn(Item { attracted: true, ..Item::new_dropped(ItemKind::Bomb, pos(0.0, 300.0), 0) });
+        assert_eq!(field.tick(&mut player), 0);
+        assert!(!field.get_items()[0].attracted);
+        assert_eq!(player.bombs, 3);
+    }
+
+    #[test]
+    fn power_stops_at_max() {
+        let field = ItemField::default();
+        let mut player = Player { power: 398, ..Default::default() };
+        field.collect(&mut player, &Item::new_dropped(ItemKind::Power, pos(0.0, 0.0), 0));
+        assert_eq!(player.power, 399);
+        field.collect(&mut player, &Item::new_dropped(ItemKind::BigPower, pos(0.0, 0.0), 0));
+        assert_eq!(player.power, player.max_power);
+    }
+
+    #[test]
+    fn point_value_by_height() {
+        let field = ItemField::default();
+        let item = Item::new_dropped(ItemKind::Point, pos(0.0, 0.0), 0);
+        assert_eq!(field.get_point_value(&item, GAME_MIN_Y), field.point_value / 2);
+        assert_eq!(field.get_point_value(&item, field.collect_line), field.point_value);
+        let auto = Item { auto_collected: true, ..item };
+        assert_eq!(field.get_point_value(&auto, GAME_MIN_Y), field.point_value);
+    }
+
+    #[test]
+    fn convert_bullets() {
+        let mut field = ItemField::default();
+        let mut bullets = BulletPool::default();
+        let template = BulletTemplate::new(0, CollideType::Circle { radius: 4.0, radius_2: 16.0 }, 2.0);
+        bullets.extend(ring(&template, pos(0.0, 100.0), 8, 0.0));
+        assert_eq!(field.convert_bullets(&mut bullets, 0), 8);
+        assert!(bullets.is_empty());
+        assert!(field.get_items().iter().all(|x| x.kind == ItemKind::Cancel && x.attracted && x.auto_collected));
+        let mut player = player_at(0.0, -400.0, false);
+        let collected = (0..100).map(|_| field.tick(&mut player)).sum::<usize>();
+        assert_eq!(collected, 8);
+        assert_eq!(player.score, 8 * field.cancel_value);
+    }
+}
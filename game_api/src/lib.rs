@@ -1,7 +1,15 @@
 pub use game::*;
 
+pub mod broadphase;
+pub mod enemy;
 pub mod game;
+mod geometry;
 pub mod input;
+pub mod item;
+pub mod pattern;
+pub mod player;
+pub mod pool;
+pub mod shot;
 
 pub const PLAYER_Z: f32 = 0.0;
 
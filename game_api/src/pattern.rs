@@ -0,0 +1,159 @@
+//! The common danmaku patterns producing batches of [`SimpleEnemyBullet`]
+//!
+//! The angles are in degrees, the right angle is zero and up is 90
+//!
+
+use crate::{CollideType, GamePos, Rotation, SimpleEnemyBullet, TexHandle};
+
+/// The shared fields of the bullets in one pattern
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct BulletTemplate {
+    pub tex: TexHandle,
+    pub collide: CollideType,
+    pub speed: f32,
+    pub a: f32,
+    pub a_delta: f32,
+    pub w: f32,
+    pub w_delta: f32,
+}
+
+impl BulletTemplate {
+    pub extern "C" fn new(tex: TexHandle, collide: CollideType, speed: f32) -> Self {
+        Self {
+            tex,
+            collide,
+            speed,
+            a: 0.0,
+            a_delta: 0.0,
+            w: 0.0,
+            w_delta: 0.0,
+        }
+    }
+
+    pub extern "C" fn spawn(&self, pos: GamePos, angle: f32) -> SimpleEnemyBullet {
+        self.spawn_with_speed(pos, angle, self.speed)
+    }
+
+    pub extern "C" fn spawn_with_speed(&self, pos: GamePos, angle: f32, speed: f32) -> SimpleEnemyBullet {
+        SimpleEnemyBullet {
+            pos,
+            tex: self.tex,
+            collide: self.collide.with_angle(angle),
+            speed,
+            rotation: Rotation::new(angle),
+            a: self.a,
+            a_delta: self.a_delta,
+            w: self.w,
+            w_delta: self.w_delta,
+            grazed: false,
+        }
+    }
+}
+
+/// The angle from one position to another
+pub extern "C" fn angle_to(from: &GamePos, to: &GamePos) -> f32 {
+    (to.y - from.y).atan2(to.x - from.x).to_degrees()
+}
+
+/// `count` bullets evenly around the center, the first one at `start_angle`
+pub fn ring(template: &BulletTemplate, center: GamePos, count: u32, start_angle: f32) -> Vec<SimpleEnemyBullet> {
+    let step = 360.0 / count.max(1) as f32;
+    (0..count).map(|i| template.spawn(center, start_angle + step * i as f32)).collect()
+}
+
+/// `count` bullets evenly in `spread` degrees around `angle`
+///
+/// One bullet is shot straight at `angle`
+pub fn fan(template: &BulletTemplate, center: GamePos, count: u32, angle: f32, spread: f32) -> Vec<SimpleEnemyBullet> {
+    if count <= 1 {
+        return (0..count).map(|_| template.spawn(center, angle)).collect();
+    }
+    let step = spread / (count - 1) as f32;
+    let start = angle - spread / 2.0;
+    (0..count).map(|i| template.spawn(center, start + step * i as f32)).collect()
+}
+
+/// The fan aimed at the target
+pub fn aimed(template: &BulletTemplate, center: GamePos, target: &GamePos, count: u32, spread: f32) -> Vec<SimpleEnemyBullet> {
+    fan(template, center, count, angle_to(&center, target), spread)
+}
+
+/// The rotating arms, fire once every some ticks
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Spiral {
+    pub arms: u32,
+    /// The angle of the first arm in next fire
+    pub angle: f32,
+    /// The angle added after each fire
+    pub angle_step: f32,
+    /// The change of the angle step after each fire
+    pub angle_step_delta: f32,
+}
+
+impl Spiral {
+    pub extern "C" fn new(arms: u32, angle: f32, angle_step: f32) -> Self {
+        Self {
+            arms,
+            angle,
+            angle_step,
+            angle_step_delta: 0.0,
+        }
+    }
+
+    /// One bullet per arm and rotate the arms
+    pub fn fire(&mut self, template: &BulletTemplate, center: GamePos) -> Vec<SimpleEnemyBullet> {
+        let bullets = ring(template, center, self.arms, self.angle);
+        self.angle = (self.angle + self.angle_step) % 360.0;
+        self.angle_step += self.angle_step_delta;
+        bullets
+    }
+}
+
+/// The seedable random for the patterns, the same seed always gives the same bullets
+///
+/// splitmix64
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct PatternRng {
+    pub state: u64,
+}
+
+impl PatternRng {
+    pub extern "C" fn new(seed: u64) -> Self {
+        Self {
+            state: seed,
+        }
+    }
+
+    pub extern "C" fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// In [0, 1)
+    pub extern "C" fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// In [min, max)
+    pub extern "C" fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+}
+
+/// `count` bullets at random angles in `spread` degrees around `angle`
+///
+/// The speed is random in [speed, speed + speed_spread) of the template
+pub fn scatter(template: &BulletTemplate, center: GamePos, count: u32, angle: f32, spread: f32,
+               speed_spread: f32, rng: &mut PatternRng) -> Vec<SimpleEnemyBullet> {
+    (0..count).map(|_| {
+        let a = angle + rng.range(-spread / 2.0, spread / 2.0);
+        let speed = rng.range(template.speed, template.speed + speed_spread);
+        template.spawn_with_speed(center, a, speed)
+    }).collect()
+}
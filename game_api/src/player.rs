@@ -0,0 +1,228 @@
+//! The movement, graze and death of the player
+//!
+//! The death goes: hit, dying for `deathbomb_ticks` in which bombing cancels the death,
+//! died for `death_ticks`, then respawn and invulnerable for `invulnerable_ticks`
+//!
+
+use crate::input::GameInputData;
+use crate::{CollideType, GAME_MAX_X, GAME_MAX_Y, GAME_MIN_X, GAME_MIN_Y, Player, PLAYER_SPAWN, SimpleEnemyBullet};
+
+/// What happened in [`Player::tick_death`] or [`Player::hit`]
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DeathEvent {
+    None,
+    /// Hit and start dying
+    Hit,
+    /// Bombed while dying and the death is cancelled
+    DeathBombed,
+    /// A life is lost
+    Died,
+    Respawned,
+    /// The last life is lost, the player stays died
+    GameOver,
+}
+
+impl Player {
+    /// The speed by focusing or not
+    pub extern "C" fn get_speed(&self) -> f32 {
+        if self.walking { self.walk_speed } else { self.move_speed }
+    }
+
+    /// Focus while the slow key is held and move by the direction, then keep the player in the field
+    ///
+    /// The player cannot move while dying or died
+    pub fn tick(&mut self, input: &GameInputData) {
+        if self.death != 0 {
+            return;
+        }
+        self.walking = input.slow > 0;
+        let (dx, dy) = input.get_move(self.get_speed());
+        self.pos.x += dx;
+        self.pos.y += dy;
+        self.clamp();
+    }
+
+    /// Keep the position `border` away from the field edges
+    ///
+    /// On the axis narrower than twice the border the player is kept at the middle
+    pub extern "C" fn clamp(&mut self) {
+        let limit = |value: f32, min: f32, max: f32| if min <= max { value.clamp(min, max) } else { (min + max) / 2.0 };
+        self.pos.x = limit(self.pos.x, GAME_MIN_X + self.border, GAME_MAX_X - self.border);
+        self.pos.y = limit(self.pos.y, GAME_MIN_Y + self.border, GAME_MAX_Y - self.border);
+    }
+
+    /// Graze the bullets within the graze radius not grazed before, return the count grazed this time
+    pub fn graze(&mut self, bullets: &mut [SimpleEnemyBullet]) -> u32 {
+        if self.death != 0 {
+            return 0;
+        }
+        let area = CollideType::Circle { radius: self.graze_radius, radius_2: self.graze_radius * self.graze_radius };
+        let mut grazed = 0;
+        for bullet in bullets.iter_mut().filter(|b| !b.grazed) {
+            if bullet.collide.is_collide_with(&bullet.pos, &area, &self.pos) {
+                bullet.grazed = true;
+                grazed += 1;
+            }
+        }
+        self.graze += grazed;
+        grazed
+    }
+
+    pub extern "C" fn can_be_hit(&self) -> bool {
+        self.death == 0 && self.invulnerable == 0
+    }
+
+    /// Start dying if can be hit, or die at once if `deathbomb_ticks` is zero
+    pub extern "C" fn hit(&mut self) -> DeathEvent {
+        if !self.can_be_hit() {
+            return DeathEvent::None;
+        }
+        if self.deathbomb_ticks <= 0 {
+            return self.die();
+        }
+        self.death = self.deathbomb_ticks;
+        DeathEvent::Hit
+    }
+
+    /// Lose a life and start the died countdown
+    fn die(&mut self) -> DeathEvent {
+        self.lives = self.lives.saturating_sub(1);
+        self.death = -self.death_ticks.max(1);
+        if self.lives == 0 { DeathEvent::GameOver } else { DeathEvent::Died }
+    }
+
+    /// Hit if any bullet collides with the hitbox
+    pub fn check_hit(&mut self, bullets: &[SimpleEnemyBullet]) -> DeathEvent {
+        if !self.can_be_hit() {
+            return DeathEvent::None;
+        }
+        let hitbox = CollideType::Circle { radius: self.radius, radius_2: self.radius * self.radius };
+        if bullets.iter().any(|b| b.collide.is_collide_with(&b.pos, &hitbox, &self.pos)) {
+            self.hit()
+        } else {
+            DeathEvent::None
+        }
+    }
+
+    /// Tick the death and invulnerable countdown, bombing just pressed while dying cancels the death
+    pub fn tick_death(&mut self, input: &GameInputData) -> DeathEvent {
+        self.invulnerable = self.invulnerable.saturating_sub(1);
+        if self.death > 0 {
+            if input.bomb == 1 && self.bombs > 0 {
+                self.bombs -= 1;
+                self.death = 0;
+                self.invulnerable = self.invulnerable_ticks;
+                return DeathEvent::DeathBombed;
+            }
+            self.death -= 1;
+            if self.death > 0 {
+                return DeathEvent::None;
+            }
+            return self.die();
+        }
+        if self.death < 0 && self.lives > 0 {
+            self.death += 1;
+            if self.death == 0 {
+                self.pos = PLAYER_SPAWN.into();
+                self.walking = false;
+                self.invulnerable = self.invulnerable_ticks;
+                return DeathEvent::Respawned;
+            }
+        }
+        DeathEvent::None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bomb() -> GameInputData {
+        GameInputData { bomb: 1, ..Default::default() }
+    }
+
+    /// Tick without input until the event other than None
+    fn tick_until_event(player: &mut Player) -> (DeathEvent, u32) {
+        let input = GameInputData::default();
+        for ticks in 1..1000 {
+            let event = player.tick_death(&input);
+            if event != DeathEvent::None {
+                return (event, ticks);
+            }
+        }
+        (DeathEvent::None, 1000)
+    }
+
+    #[test]
+    fn deathbomb_on_first_tick() {
+        let mut player = Player::default();
+        assert_eq!(player.hit(), DeathEvent::Hit);
+        assert_eq!(player.tick_death(&bomb()), DeathEvent::DeathBombed);
+        assert_eq!((player.death, player.lives, player.bombs), (0, 3, 2));
+        assert_eq!(player.invulnerable, player.invulnerable_ticks);
+    }
+
+    #[test]
+    fn deathbomb_on_last_tick() {
+        let mut player = Player::default();
+        player.hit();
+        for _ in 1..player.deathbomb_ticks {
+            assert_eq!(player.tick_death(&GameInputData::default()), DeathEvent::None);
+        }
+        assert_eq!(player.tick_death(&bomb()), DeathEvent::DeathBombed);
+        assert_eq!(player.lives, 3);
+    }
+
+    #[test]
+    fn deathbomb_too_late() {
+        let mut player = Player::default();
+        player.hit();
+        assert_eq!(tick_until_event(&mut player), (DeathEvent::Died, player.deathbomb_ticks as u32));
+        assert_eq!(player.tick_death(&bomb()), DeathEvent::None);
+        assert_eq!((player.lives, player.bombs), (2, 3));
+    }
+
+    #[test]
+    fn no_deathbomb_window() {
+        let mut player = Player { deathbomb_ticks: 0, ..Default::default() };
+        assert_eq!(player.hit(), DeathEvent::Died);
+        assert_eq!(player.lives, 2);
+        assert_eq!(player.tick_death(&bomb()), DeathEvent::None);
+        assert_eq!(player.bombs, 3);
+    }
+
+    #[test]
+    fn respawn() {
+        let mut player = Player { pos: (100.0, 100.0, PLAYER_SPAWN.2).into(), walking: true, ..Default::default() };
+        player.hit();
+        tick_until_event(&mut player);
+        assert_eq!(tick_until_event(&mut player), (DeathEvent::Respawned, player.death_ticks as u32));
+        assert_eq!(player.pos, PLAYER_SPAWN.into());
+        assert!(!player.walking);
+        assert_eq!(player.invulnerable, player.invulnerable_ticks);
+        assert_eq!(player.death, 0);
+    }
+
+    #[test]
+    fn game_over() {
+        let mut player = Player { lives: 1, ..Default::default() };
+        player.hit();
+        assert_eq!(tick_until_event(&mut player).0, DeathEvent::GameOver);
+        let death = player.death;
+        assert_eq!(tick_until_event(&mut player).0, DeathEvent::None);
+        assert_eq!((player.death, player.lives), (death, 0));
+        assert!(!player.can_be_hit());
+    }
+
+    #[test]
+    fn invulnerable() {
+        let mut player = Player { invulnerable: 2, ..Default::default() };
+        assert_eq!(player.hit(), DeathEvent::None);
+        let bullets = [SimpleEnemyBullet::new(player.pos, 0, CollideType::Circle { radius: 4.0, radius_2: 16.0 }, 0.0, 0.0)];
+        assert_eq!(player.check_hit(&bullets), DeathEvent::None);
+        player.tick_death(&GameInputData::default());
+        player.tick_death(&GameInputData::default());
+        assert_eq!(player.check_hit(&bullets), DeathEvent::Hit);
+    }
+}
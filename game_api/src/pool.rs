@@ -0,0 +1,178 @@
+//! The container of the enemy bullets removing the ones out of the field or too old
+//!
+//! The removed slots are filled by moving the bullets after them forward, so the order is kept
+//! and the storage is reused for the bullets spawned later
+//!
+
+use crate::{GAME_MAX_X, GAME_MAX_Y, GAME_MIN_X, GAME_MIN_Y, GamePos, SimpleEnemyBullet};
+
+/// Whether the position is in the play field extended by the margin
+pub extern "C" fn is_in_field(pos: &GamePos, margin: f32) -> bool {
+    pos.x >= GAME_MIN_X - margin && pos.x <= GAME_MAX_X + margin && pos.y >= GAME_MIN_Y - margin && pos.y <= GAME_MAX_Y + margin
+}
+
+pub struct BulletPool {
+    bullets: Vec<SimpleEnemyBullet>,
+    /// The ticks lived of every bullet
+    ages: Vec<u32>,
+    /// How far out of the field the bullets are kept, for the bullets coming back
+    pub margin: f32,
+    /// The bullets are removed after these ticks, `u32::MAX` for forever
+    pub max_age: u32,
+}
+
+impl BulletPool {
+    pub fn new(margin: f32, max_age: u32) -> Self {
+        Self::with_capacity(margin, max_age, 0)
+    }
+
+    pub fn with_capacity(margin: f32, max_age: u32, capacity: usize) -> Self {
+        Self {
+            bullets: Vec::with_capacity(capacity),
+            ages: Vec::with_capacity(capacity),
+            margin,
+            max_age,
+        }
+    }
+
+    pub fn spawn(&mut self, bullet: SimpleEnemyBullet) {
+        self.bullets.push(bullet);
+        self.ages.push(0);
+    }
+
+    pub fn get_bullets(&self) -> &[SimpleEnemyBullet] {
+        &self.bullets
+    }
+
+    pub fn get_bullets_mut(&mut self) -> &mut [SimpleEnemyBullet] {
+        &mut self.bullets
+    }
+
+    /// The ticks lived of the bullet at the index
+    pub fn get_age(&self, index: usize) -> Option<u32> {
+        self.ages.get(index).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.bullets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bullets.is_empty()
+    }
+
+    /// Remove all bullets and keep the storage
+    pub fn clear(&mut self) {
+        self.bullets.clear();
+        self.ages.clear();
+    }
+
+    /// Keep the bullets by the function with the bullet and its age in order, return the count removed
+    pub fn retain(&mut self, mut keep: impl FnMut(&SimpleEnemyBullet, u32) -> bool) -> usize {
+        let len = self.bullets.len();
+        let mut kept = 0;
+        for i in 0..len {
+            if keep(&self.bullets[i], self.ages[i]) {
+                self.bullets.swap(kept, i);
+                self.ages.swap(kept, i);
+                kept += 1;
+            }
+        }
+        self.bullets.truncate(kept);
+        self.ages.truncate(kept);
+        len - kept
+    }
+
+    /// Tick all bullets and remove the ones out of the margin or past the max age, return the count removed
+    pub fn tick(&mut self) -> usize {
+        for (bullet, age) in self.bullets.iter_mut().zip(self.ages.iter_mut()) {
+            bullet.tick();
+            *age = age.saturating_add(1);
+        }
+        let (margin, max_age) = (self.margin, self.max_age);
+        self.retain(|bullet, age| (max_age == u32::MAX || age < max_age) && is_in_field(&bullet.pos, margin))
+    }
+}
+
+impl Extend<SimpleEnemyBullet> for BulletPool {
+    fn extend<T: IntoIterator<Item=SimpleEnemyBullet>>(&mut self, iter: T) {
+        for bullet in iter {
+            self.spawn(bullet);
+        }
+    }
+}
+
+impl Default for BulletPool {
+    fn default() -> Self {
+        Self::new(64.0, u32::MAX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CollideType, TexHandle};
+
+    /// The bullet with the tex as its id, moving right by the speed
+    fn bullet(id: TexHandle, x: f32, speed: f32) -> SimpleEnemyBullet {
+        SimpleEnemyBullet::new((x, 0.0, 0.0).into(), id, CollideType::Circle { radius: 4.0, radius_2: 16.0 }, speed, 0.0)
+    }
+
+    fn ids(pool: &BulletPool) -> Vec<TexHandle> {
+        pool.get_bullets().iter().map(|x| x.tex).collect()
+    }
+
+    #[test]
+    fn retain_keeps_order() {
+        let mut pool = BulletPool::default();
+        pool.extend((0..6).map(|id| bullet(id, 0.0, 0.0)));
+        assert_eq!(pool.retain(|bullet, _| bullet.tex % 2 == 1), 3);
+        assert_eq!(ids(&pool), vec![1, 3, 5]);
+    }
+
+    #[test]
+    fn ages_follow_bullets() {
+        let mut pool = BulletPool::default();
+        pool.spawn(bullet(0, 0.0, 0.0));
+        pool.tick();
+        pool.spawn(bullet(1, 0.0, 0.0));
+        pool.tick();
+        pool.spawn(bullet(2, 0.0, 0.0));
+        assert_eq!(pool.retain(|bullet, _| bullet.tex != 0), 1);
+        assert_eq!(ids(&pool), vec![1, 2]);
+        assert_eq!((pool.get_age(0), pool.get_age(1), pool.get_age(2)), (Some(1), Some(0), None));
+    }
+
+    #[test]
+    fn tick_culls_old_bullets() {
+        let mut pool = BulletPool::new(64.0, 3);
+        pool.spawn(bullet(0, 0.0, 0.0));
+        assert_eq!(pool.tick(), 0);
+        pool.spawn(bullet(1, 0.0, 0.0));
+        assert_eq!(pool.tick(), 0);
+        assert_eq!(pool.tick(), 1);
+        assert_eq!(ids(&pool), vec![1]);
+        assert_eq!(pool.get_age(0), Some(2));
+    }
+
+    #[test]
+    fn tick_culls_by_margin() {
+        let mut pool = BulletPool::new(10.0, u32::MAX);
+        pool.extend([bullet(0, GAME_MAX_X, 8.0), bullet(1, GAME_MAX_X, 4.0), bullet(2, GAME_MAX_X + 20.0, -30.0)]);
+        assert_eq!(pool.tick(), 0);
+        assert_eq!(ids(&pool), vec![0, 1, 2]);
+        assert_eq!(pool.tick(), 1);
+        assert_eq!(ids(&pool), vec![1, 2]);
+    }
+
+    #[test]
+    fn clear_keeps_capacity() {
+        let mut pool = BulletPool::with_capacity(64.0, u32::MAX, 0);
+        pool.extend((0..100).map(|id| bullet(id, 0.0, 0.0)));
+        let capacity = (pool.bullets.capacity(), pool.ages.capacity());
+        pool.clear();
+        assert!(pool.is_empty());
+        assert_eq!((pool.bullets.capacity(), pool.ages.capacity()), capacity);
+        assert!(capacity.0 >= 100 && capacity.1 >= 100);
+    }
+}
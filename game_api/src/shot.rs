@@ -0,0 +1,134 @@
+//! The shot types of the player emitting [`PlayerBullet`]s and the damage to the enemy hitboxes
+//!
+
+use crate::input::GameInputData;
+use crate::pool::is_in_field;
+use crate::{CollideType, GamePos, Player, PlayerBullet, TexHandle};
+
+/// The bullets emitted once
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ShotPattern {
+    pub tex: TexHandle,
+    /// The count of the bullets
+    pub ways: u32,
+    /// The degrees of the fan around up, zero for parallel bullets
+    pub spread: f32,
+    /// The distance between the parallel bullets
+    pub gap: f32,
+    pub speed: f32,
+    pub damage: f32,
+    pub radius: f32,
+    /// See [`PlayerBullet::homing`]
+    pub homing: f32,
+}
+
+impl ShotPattern {
+    /// Emit the bullets from the position to the output
+    pub fn emit(&self, pos: &GamePos, out: &mut Vec<PlayerBullet>) {
+        let ways = self.ways.max(1);
+        for i in 0..ways {
+            // from -0.5 to 0.5 over the ways
+            let t = if ways == 1 { 0.0 } else { i as f32 / (ways - 1) as f32 - 0.5 };
+            let (sin, cos) = (90.0 + self.spread * t).to_radians().sin_cos();
+            out.push(PlayerBullet {
+                pos: (pos.x + self.gap * (ways - 1) as f32 * t, pos.y, pos.z).into(),
+                tex: self.tex,
+                damage: self.damage,
+                vx: cos * self.speed,
+                vy: sin * self.speed,
+                radius: self.radius,
+                homing: self.homing,
+            });
+        }
+    }
+}
+
+/// The shot of the player, different patterns when focused or not
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ShotType {
+    pub unfocused: ShotPattern,
+    pub focused: ShotPattern,
+    /// The ticks between two shots
+    pub cooldown: u8,
+}
+
+impl ShotType {
+    /// The wide fan and the narrow straight lines when focused
+    pub extern "C" fn spread(tex: TexHandle) -> Self {
+        let focused = ShotPattern { tex, ways: 4, spread: 0.0, gap: 10.0, speed: 24.0, damage: 3.0, radius: 8.0, homing: 0.0 };
+        Self {
+            unfocused: ShotPattern { ways: 5, spread: 30.0, gap: 0.0, damage: 2.0, ..focused },
+            focused,
+            cooldown: 4,
+        }
+    }
+
+    /// The slow bullets turning to the enemies, and fast less homing ones when focused
+    pub extern "C" fn homing(tex: TexHandle) -> Self {
+        let unfocused = ShotPattern { tex, ways: 4, spread: 60.0, gap: 0.0, speed: 14.0, damage: 1.5, radius: 8.0, homing: 8.0 };
+        Self {
+            unfocused,
+            focused: ShotPattern { ways: 2, spread: 10.0, speed: 20.0, damage: 2.5, homing: 3.0, ..unfocused },
+            cooldown: 5,
+        }
+    }
+}
+
+impl Player {
+    /// Emit the bullets while shoot is held and the cooldown is over, return the count emitted
+    pub fn shoot(&mut self, input: &GameInputData, shot: &ShotType, out: &mut Vec<PlayerBullet>) -> usize {
+        self.shoot_cooldown = self.shoot_cooldown.saturating_sub(1);
+        if input.shoot == 0 || self.shoot_cooldown > 0 || self.death != 0 {
+            return 0;
+        }
+        let pattern = if self.walking { &shot.focused } else { &shot.unfocused };
+        let len = out.len();
+        pattern.emit(&self.pos, out);
+        self.shoot_cooldown = shot.cooldown;
+        out.len() - len
+    }
+}
+
+impl PlayerBullet {
+    /// Turn to the target if homing and move
+    pub extern "C" fn tick(&mut self, target: Option<&GamePos>) {
+        if let (Some(target), true) = (target, self.homing > 0.0) {
+            let speed = self.vx.hypot(self.vy);
+            let angle = self.vy.atan2(self.vx).to_degrees();
+            let want = (target.y - self.pos.y).atan2(target.x - self.pos.x).to_degrees();
+            // the shortest turn in (-180, 180]
+            let turn = (want - angle + 540.0).rem_euclid(360.0) - 180.0;
+            let (sin, cos) = (angle + turn.clamp(-self.homing, self.homing)).to_radians().sin_cos();
+            self.vx = cos * speed;
+            self.vy = sin * speed;
+        }
+        self.pos.x += self.vx;
+        self.pos.y += self.vy;
+    }
+}
+
+/// Tick the bullets homing to the nearest hitbox, the bullets hitting a hitbox are removed and
+/// their damage is added to the damage of the hitbox at the same index
+///
+/// The bullets out of the field by the margin are removed
+pub fn tick_player_bullets(bullets: &mut Vec<PlayerBullet>, hitboxes: &[(CollideType, GamePos)], damage: &mut [f32], margin: f32) {
+    bullets.retain_mut(|bullet| {
+        let nearest = hitboxes.iter().map(|(_, pos)| pos).min_by(|a, b| {
+            let distance = |p: &GamePos| (p.x - bullet.pos.x).powi(2) + (p.y - bullet.pos.y).powi(2);
+            distance(a).total_cmp(&distance(b))
+        });
+        bullet.tick(nearest);
+        let collide = CollideType::Circle { radius: bullet.radius, radius_2: bullet.radius * bullet.radius };
+        match hitboxes.iter().position(|(hitbox, pos)| hitbox.is_collide_with(pos, &collide, &bullet.pos)) {
+            Some(i) => {
+                if let Some(d) = damage.get_mut(i) {
+                    *d += bullet.damage;
+                }
+                false
+            }
+            None => is_in_field(&bullet.pos, margin),
+        }
+    });
+}
@@ -0,0 +1,102 @@
+use std::convert::TryFrom;
+
+use game_api::{CollideType, GamePos};
+use proptest::prelude::*;
+
+fn pos(x: f32, y: f32) -> GamePos {
+    (x, y, 0.0).into()
+}
+
+fn circle(radius: f32) -> CollideType {
+    CollideType::Circle { radius, radius_2: radius * radius }
+}
+
+fn coord() -> impl Strategy<Value=f32> {
+    -100.0f32..100.0
+}
+
+fn shape() -> impl Strategy<Value=CollideType> {
+    prop_oneof![
+        (0.5f32..30.0).prop_map(circle),
+        (0.5f32..30.0, 0.5f32..30.0).prop_map(|(w, h)| CollideType::try_from((11, vec![w, h])).unwrap()),
+        (0.5f32..30.0, 0.5f32..30.0, 0.0f32..360.0).prop_map(|(w, h, a)| CollideType::try_from((12, vec![w, h, a])).unwrap()),
+        (0.0f32..30.0, 0.5f32..10.0, 0.0f32..360.0).prop_map(|(l, r, a)| CollideType::try_from((13, vec![l, r, a])).unwrap()),
+        (0.0f32..60.0, 0.5f32..10.0, 0.0f32..360.0).prop_map(|(l, r, a)| CollideType::try_from((14, vec![l, r, a])).unwrap()),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn circles_overlap_by_radius_sum(r1 in 0.5f32..50.0, r2 in 0.5f32..50.0, x in coord(), y in coord()) {
+        let distance = (x * x + y * y).sqrt();
+        prop_assume!((distance - (r1 + r2)).abs() > 1e-3);
+        let collide = circle(r1).is_collide_with(&pos(0.0, 0.0), &circle(r2), &pos(x, y));
+        prop_assert_eq!(collide, distance < r1 + r2);
+    }
+
+    #[test]
+    fn circle_contains_point_by_radius(r in 0.5f32..50.0, x in coord(), y in coord()) {
+        let distance = (x * x + y * y).sqrt();
+        prop_assume!((distance - r).abs() > 1e-3);
+        prop_assert_eq!(circle(r).is_collide_with_point(&pos(0.0, 0.0), &pos(x, y)), distance < r);
+    }
+
+    #[test]
+    fn collide_is_symmetric(a in shape(), b in shape(), x1 in coord(), y1 in coord(), x2 in coord(), y2 in coord()) {
+        let (p1, p2) = (pos(x1, y1), pos(x2, y2));
+        prop_assert_eq!(a.is_collide_with(&p1, &b, &p2), b.is_collide_with(&p2, &a, &p1));
+    }
+
+    #[test]
+    fn zero_circle_is_point(a in shape(), x1 in coord(), y1 in coord(), x2 in coord(), y2 in coord()) {
+        let (p1, p2) = (pos(x1, y1), pos(x2, y2));
+        prop_assume!(!matches!(a, CollideType::Circle { .. }));
+        prop_assert_eq!(a.is_collide_with(&p1, &circle(0.0), &p2), a.is_collide_with_point(&p1, &p2));
+    }
+
+    #[test]
+    fn shape_contains_own_position(a in shape(), x in coord(), y in coord()) {
+        prop_assert!(a.is_collide_with_point(&pos(x, y), &pos(x, y)));
+        prop_assert!(a.is_collide_with(&pos(x, y), &circle(0.5), &pos(x, y)));
+    }
+
+    #[test]
+    fn point_up_covers_moved_shape(a in shape(), x in coord(), y in coord(), up in 0.0f32..200.0, t in 0.0f32..1.0,
+                                   dx in -1.0f32..1.0, dy in -1.0f32..1.0) {
+        // a point near the center of the shape moved up by some of `up`
+        let moved = pos(x, y + up * t);
+        let me = pos(moved.x + dx * 0.2, moved.y + dy * 0.2);
+        prop_assume!(a.is_collide_with_point(&moved, &me));
+        prop_assert!(a.is_collide_with_point_up(&me, &pos(x, y), up));
+    }
+
+    #[test]
+    fn point_up_grows_with_up(a in shape(), x in coord(), y in coord(), up in 0.0f32..200.0, more in 0.0f32..200.0) {
+        let me = pos(0.0, 0.0);
+        if a.is_collide_with_point_up(&me, &pos(x, y), up) {
+            prop_assert!(a.is_collide_with_point_up(&me, &pos(x, y), up + more));
+        }
+    }
+
+    #[test]
+    fn decoder_never_panics(byte in any::<u8>(), args in proptest::collection::vec(-100.0f32..100.0, 0..5)) {
+        let count = CollideType::get_arg_count(byte);
+        let result = CollideType::try_from((byte, args.clone()));
+        prop_assert_eq!(result.is_ok(), count != 0 && args.len() >= count);
+    }
+}
+
+#[test]
+fn oriented_bullet_faces_its_direction() {
+    let capsule = CollideType::try_from((13, vec![10.0, 2.0, 0.0])).unwrap();
+    let template = game_api::pattern::BulletTemplate::new(0, capsule, 0.0);
+    let mut bullet = template.spawn(pos(0.0, 0.0), 90.0);
+    let point = circle(0.0);
+    assert!(bullet.collide.is_collide_with(&bullet.pos, &point, &pos(0.0, 9.0)));
+    assert!(!bullet.collide.is_collide_with(&bullet.pos, &point, &pos(9.0, 0.0)));
+
+    bullet.w = -90.0;
+    bullet.tick();
+    assert!(bullet.collide.is_collide_with(&bullet.pos, &point, &pos(9.0, 0.0)));
+    assert!(!bullet.collide.is_collide_with(&bullet.pos, &point, &pos(0.0, 9.0)));
+}
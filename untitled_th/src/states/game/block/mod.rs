@@ -8,6 +8,31 @@ pub struct Block {
     bullet_bounding: Bounding,
 }
 
+/// What is moving across the blocks.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Mover {
+    /// Stopped by the pass bounding.
+    Walker,
+    /// Stopped by the bullet bounding.
+    Bullet,
+}
+
+impl Block {
+    pub fn new(pass_bounding: Bounding, bullet_bounding: Bounding) -> Self {
+        Self {
+            pass_bounding,
+            bullet_bounding,
+        }
+    }
+
+    pub fn get_bounding(&self, mover: Mover) -> Bounding {
+        match mover {
+            Mover::Walker => self.pass_bounding,
+            Mover::Bullet => self.bullet_bounding,
+        }
+    }
+}
+
 /// The entity is fixed and cannot be move in any way (Such as background..?)
 pub struct Fixed;
 
@@ -0,0 +1,86 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+
+use futures::executor::ThreadPool;
+use uth_map::chunk::{ChunkedMap, ChunkedMapHeader, MapChunk};
+use uth_map::{MapField, MapParseError};
+
+/// The chunks within this many chunks around the camera are loaded.
+const LOAD_MARGIN: u32 = 1;
+/// The chunks farther than this many chunks from the camera are unloaded.
+/// Larger than the load margin so the chunks on the border are not loaded again and again.
+const KEEP_MARGIN: u32 = 2;
+
+type ChunkResult = ((u32, u32), Result<MapChunk, MapParseError>);
+
+/// Load the chunks of the chunked map around the camera on the io pool.
+pub struct ChunkLoader {
+    path: Arc<PathBuf>,
+    map: ChunkedMap,
+    pending: HashSet<(u32, u32)>,
+    /// The chunks failed to read, they are left empty and not read again.
+    failed: HashSet<(u32, u32)>,
+    sender: Sender<ChunkResult>,
+    receiver: Receiver<ChunkResult>,
+}
+
+impl ChunkLoader {
+    /// Read the header of the chunked map file, no chunk is loaded.
+    pub fn open(path: PathBuf) -> Result<Self, MapParseError> {
+        let file = std::fs::File::open(&path).map_err(|source| MapParseError::Io { field: MapField::Header, offset: 0, source })?;
+        let header = ChunkedMapHeader::read_from(file)?;
+        let (sender, receiver) = channel();
+        Ok(Self {
+            path: Arc::new(path),
+            map: ChunkedMap::new(Arc::new(header)),
+            pending: HashSet::new(),
+            failed: HashSet::new(),
+            sender,
+            receiver,
+        })
+    }
+
+    pub fn get_map(&self) -> &ChunkedMap {
+        &self.map
+    }
+
+    /// Take the chunks read, request the chunks around the camera and unload the chunks far away.
+    /// The camera is the rectangle in slots from the left top.
+    pub fn update(&mut self, io_pool: &ThreadPool, camera_min: (f32, f32), camera_max: (f32, f32)) {
+        for (chunk, result) in self.receiver.try_iter() {
+            self.pending.remove(&chunk);
+            match result {
+                Ok(data) => self.map.insert_chunk(chunk, data),
+                Err(e) => {
+                    log::warn!("Read chunk {:?} of map {:?} failed for {}", chunk, self.path, e);
+                    self.failed.insert(chunk);
+                }
+            }
+        }
+
+        let header = self.map.get_header().clone();
+        let keep = header.chunks_in(camera_min, camera_max, KEEP_MARGIN).into_iter().collect::<HashSet<_>>();
+        let far = self.map.loaded_chunks().filter(|x| !keep.contains(x)).collect::<Vec<_>>();
+        for chunk in far {
+            self.map.remove_chunk(chunk);
+        }
+
+        for chunk in header.chunks_in(camera_min, camera_max, LOAD_MARGIN) {
+            if self.map.is_loaded(chunk) || self.failed.contains(&chunk) || !self.pending.insert(chunk) {
+                continue;
+            }
+            let header = header.clone();
+            let path = self.path.clone();
+            let sender = self.sender.clone();
+            io_pool.spawn_ok(async move {
+                let result = std::fs::File::open(path.as_ref())
+                    .map_err(|source| MapParseError::Io { field: MapField::ChunkIndex { x: chunk.0, y: chunk.1 }, offset: 0, source })
+                    .and_then(|file| header.read_chunk(file, chunk));
+                // the loader is dropped when the receiver is gone.
+                let _ = sender.send((chunk, result));
+            });
+        }
+    }
+}
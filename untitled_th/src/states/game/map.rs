@@ -1,4 +1,8 @@
-use specs::Entity;
+use specs::{Entity, ReadStorage};
+use uth_map::bound::Bounding;
+use uth_map::collide::Movement;
+
+use super::block::{Block, Mover};
 
 /// The map in the game.
 /// The left top is zero point
@@ -25,3 +29,13 @@ impl FixedMap {
         }
     }
 }
+
+/// Move the point across the map, stopped by the bounding of the blocks for the mover.
+/// The position is in blocks from the left top and the outside of the map is free.
+pub fn resolve_move(map: &impl Map, blocks: &ReadStorage<Block>, mover: Mover, pos: (f32, f32), velocity: (f32, f32)) -> Movement {
+    uth_map::collide::resolve_move(|x, y| {
+        map.get_block(x as i32, y as i32)
+            .and_then(|e| blocks.get(*e))
+            .map_or(Bounding::No, |b| b.get_bounding(mover))
+    }, pos, velocity)
+}
@@ -4,6 +4,7 @@ use crate::{GameState, StateData};
 
 mod map;
 mod block;
+mod chunk;
 
 pub struct Health {
     hp: i32,
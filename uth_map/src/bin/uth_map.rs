@@ -0,0 +1,54 @@
+//! Convert the uth maps between the binary and the text form, and diff or patch them.
+//!
+//! ```text
+//! uth_map to-text <map.uth> <map.txt>
+//! uth_map to-bin <map.txt> <map.uth>
+//! uth_map diff <old.uth> <new.uth> <changes.diff>
+//! uth_map patch <map.uth> <changes.diff> <out.uth>
+//! uth_map compress <map.uth> <out.uth>
+//! ```
+
+use std::fs::File;
+
+use uth_map::diff::MapDiff;
+use uth_map::{FixedUthMapData, SlotEncoding};
+
+const USAGE: &str = "Usage:
+    uth_map to-text <map.uth> <map.txt>
+    uth_map to-bin <map.txt> <map.uth>
+    uth_map diff <old.uth> <new.uth> <changes.diff>
+    uth_map patch <map.uth> <changes.diff> <out.uth>
+    uth_map compress <map.uth> <out.uth>";
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    match args.iter().map(|x| x.as_str()).collect::<Vec<_>>()[..] {
+        ["to-text", input, output] => {
+            let map = FixedUthMapData::from_file(File::open(input)?)?;
+            std::fs::write(output, map.to_text())?;
+        }
+        ["to-bin", input, output] => {
+            let map = FixedUthMapData::from_text(&std::fs::read_to_string(input)?)?;
+            map.to_file(File::create(output)?)?;
+        }
+        ["diff", old, new, output] => {
+            let old = FixedUthMapData::from_file(File::open(old)?)?;
+            let new = FixedUthMapData::from_file(File::open(new)?)?;
+            std::fs::write(output, old.diff(&new)?.to_text())?;
+        }
+        ["patch", input, diff, output] => {
+            let mut map = FixedUthMapData::from_file(File::open(input)?)?;
+            MapDiff::from_text(&std::fs::read_to_string(diff)?)?.apply(&mut map)?;
+            map.to_file(File::create(output)?)?;
+        }
+        ["compress", input, output] => {
+            let map = FixedUthMapData::from_file(File::open(input)?)?;
+            map.to_file_encoded(File::create(output)?, SlotEncoding::RunLength)?;
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    }
+    Ok(())
+}
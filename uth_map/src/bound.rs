@@ -4,7 +4,7 @@ pub const LEFT_VALUE: u8 = 0b0010;
 pub const RIGHT_VALUE: u8 = 0b0001;
 
 #[repr(u8)]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Direction {
     Left = LEFT_VALUE,
     Right = RIGHT_VALUE,
@@ -13,7 +13,7 @@ pub enum Direction {
 }
 
 #[repr(u8)]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Bounding {
     No = 0,
     Left = LEFT_VALUE,
@@ -33,9 +33,50 @@ pub enum Bounding {
     AllBlock = 0b1111,
 }
 
+impl Direction {
+    pub const ALL: [Direction; 4] = [Direction::Up, Direction::Down, Direction::Left, Direction::Right];
+
+    /// The offset in the map, the left top is zero point so up is y - 1.
+    pub fn offset(self) -> (isize, isize) {
+        match self {
+            Direction::Left => (-1, 0),
+            Direction::Right => (1, 0),
+            Direction::Up => (0, -1),
+            Direction::Down => (0, 1),
+        }
+    }
+}
+
 impl Bounding {
     /// Check the to direction bound, true if has
     pub fn check_bound(self, to: Direction) -> bool {
         (to as u8 & self as u8) != 0
     }
 }
+
+impl TryFrom<u8> for Bounding {
+    type Error = u8;
+
+    /// Decode the bounding byte, only the low 4 bits can be set.
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => Bounding::No,
+            LEFT_VALUE => Bounding::Left,
+            RIGHT_VALUE => Bounding::Right,
+            UP_VALUE => Bounding::Up,
+            DOWN_VALUE => Bounding::Down,
+            0b1010 => Bounding::LeftUp,
+            0b1001 => Bounding::RightUp,
+            0b0110 => Bounding::LeftDown,
+            0b0101 => Bounding::RightDown,
+            0b1100 => Bounding::UpDown,
+            0b0011 => Bounding::LeftRight,
+            0b0111 => Bounding::UpPass,
+            0b1101 => Bounding::LeftPass,
+            0b1110 => Bounding::RightPass,
+            0b1011 => Bounding::DownPass,
+            0b1111 => Bounding::AllBlock,
+            _ => return Err(value),
+        })
+    }
+}
@@ -0,0 +1,368 @@
+//! The map split into chunks for the long stages.
+//!
+//! The chunked file has the block table and the chunk index at the start,
+//! so every chunk can be read alone by seeking to it.
+//! See readme.md for the layout.
+
+use std::collections::HashMap;
+use std::io::{BufReader, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+
+use byteorder::{WriteBytesExt, BE};
+
+use crate::error::{MapField, MapParseError};
+use crate::format::{read_blocks, write_blocks};
+use crate::read::{MapReader, PREALLOCATE_LIMIT};
+use crate::{BlockInfo, FixedUthMapData, GameMap, Layer};
+
+/// The version written by [`write_chunked`].
+pub const CHUNK_VERSION: u16 = 1;
+
+/// The geometry, the block table and the chunk index of the chunked map file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChunkedMapHeader {
+    width: u32,
+    height: u32,
+    chunk_width: u32,
+    chunk_height: u32,
+    chunks_x: u32,
+    chunks_y: u32,
+    blocks: Vec<BlockInfo>,
+    /// The offset from the file start and the length of every chunk, row by row.
+    /// The length is 0 for the empty chunk.
+    index: Vec<(u64, u32)>,
+}
+
+/// The slots of the layers in one chunk, stored row by row from the left top of the chunk.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapChunk {
+    layers: [Option<Vec<u32>>; 3],
+}
+
+impl MapChunk {
+    /// None if the slots cannot be held in memory.
+    fn empty(slots: usize) -> Option<Self> {
+        let mut terrain = Vec::new();
+        terrain.try_reserve_exact(slots).ok()?;
+        terrain.resize(slots, 0);
+        Some(Self {
+            layers: [None, Some(terrain), None],
+        })
+    }
+
+    /// Get the slots of the layer, None if the chunk does not have the layer.
+    pub fn get_layer(&self, layer: Layer) -> Option<&[u32]> {
+        self.layers[layer as usize].as_deref()
+    }
+}
+
+impl ChunkedMapHeader {
+    /// Read the header from the start of the chunked map file.
+    /// The reader will be buffered internally, the chunks are read by [`Self::read_chunk`].
+    pub fn read_from<R: Read>(reader: R) -> Result<Self, MapParseError> {
+        let mut reader = MapReader::new(BufReader::new(reader));
+        let mut header = [0; 4];
+        if reader.read_exact(&mut header, MapField::Header).is_err() || &header != b"uthc" {
+            return Err(MapParseError::NotMapFile);
+        }
+        let version = reader.read_u16(MapField::Version)?;
+        if version != CHUNK_VERSION {
+            return Err(MapParseError::UnsupportedVersion { version });
+        }
+        let width = reader.read_u32(MapField::Width)?;
+        let height = reader.read_u32(MapField::Height)?;
+        let chunk_width = reader.read_u32(MapField::ChunkWidth)?;
+        let chunk_height = reader.read_u32(MapField::ChunkHeight)?;
+        if chunk_width == 0 || chunk_height == 0 {
+            return Err(MapParseError::SizeOverflow { width: chunk_width, height: chunk_height });
+        }
+        (chunk_width as usize).checked_mul(chunk_height as usize).ok_or(MapParseError::SizeOverflow { width: chunk_width, height: chunk_height })?;
+        let blocks = reader.read_u32(MapField::BlockCount)?;
+        let blocks = read_blocks(&mut reader, blocks, &mut Vec::new())?;
+
+        let chunks_x = width.div_ceil(chunk_width);
+        let chunks_y = height.div_ceil(chunk_height);
+        let chunks = (chunks_x as usize).checked_mul(chunks_y as usize).ok_or(MapParseError::SizeOverflow { width, height })?;
+        let mut index = Vec::with_capacity(chunks.min(PREALLOCATE_LIMIT));
+        for y in 0..chunks_y {
+            for x in 0..chunks_x {
+                let offset = reader.read_u64(MapField::ChunkIndex { x, y })?;
+                let len = reader.read_u32(MapField::ChunkIndex { x, y })?;
+                index.push((offset, len));
+            }
+        }
+        Ok(Self {
+            width,
+            height,
+            chunk_width,
+            chunk_height,
+            chunks_x,
+            chunks_y,
+            blocks,
+            index,
+        })
+    }
+
+    pub fn get_size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn get_chunk_size(&self) -> (u32, u32) {
+        (self.chunk_width, self.chunk_height)
+    }
+
+    pub fn get_chunk_count(&self) -> (u32, u32) {
+        (self.chunks_x, self.chunks_y)
+    }
+
+    pub fn get_blocks(&self) -> &[BlockInfo] {
+        &self.blocks
+    }
+
+    /// Get the chunks overlapping the slot rectangle from `min` to `max` and `margin` chunks around it.
+    pub fn chunks_in(&self, min: (f32, f32), max: (f32, f32), margin: u32) -> Vec<(u32, u32)> {
+        if self.chunks_x == 0 || self.chunks_y == 0 {
+            return Vec::new();
+        }
+        let range = |min: f32, max: f32, chunk_size: u32, chunks: u32| {
+            let first = (min / chunk_size as f32).floor().max(0.0) as u32;
+            let last = ((max / chunk_size as f32).floor().max(0.0) as u32).min(chunks - 1);
+            (first.saturating_sub(margin).min(chunks - 1), last.saturating_add(margin).min(chunks - 1))
+        };
+        let (x0, x1) = range(min.0, max.0, self.chunk_width, self.chunks_x);
+        let (y0, y1) = range(min.1, max.1, self.chunk_height, self.chunks_y);
+        (y0..=y1).flat_map(|y| (x0..=x1).map(move |x| (x, y))).collect()
+    }
+
+    /// Read the chunk by seeking in the chunked map file.
+    /// Every chunk can be read with its own reader so they can be loaded in parallel.
+    pub fn read_chunk<R: Read + Seek>(&self, mut reader: R, (x, y): (u32, u32)) -> Result<MapChunk, MapParseError> {
+        if x >= self.chunks_x || y >= self.chunks_y {
+            return Err(MapParseError::NoSuchChunk { x, y });
+        }
+        let slots = self.chunk_width as usize * self.chunk_height as usize;
+        let (offset, len) = self.index[y as usize * self.chunks_x as usize + x as usize];
+        if len == 0 {
+            return MapChunk::empty(slots).ok_or(MapParseError::SizeOverflow { width: self.chunk_width, height: self.chunk_height });
+        }
+        reader.seek(SeekFrom::Start(offset)).map_err(|source| MapParseError::Io { field: MapField::ChunkIndex { x, y }, offset, source })?;
+        let mut reader = MapReader::with_offset(BufReader::new(reader.take(len as u64)), offset);
+        let layer_offset = reader.offset();
+        let mask = reader.read_u8(MapField::ChunkLayers { x, y })?;
+        if mask & (1 << Layer::Terrain as u8) == 0 || mask >> Layer::ALL.len() != 0 {
+            return Err(MapParseError::InvalidLayer { value: mask, offset: layer_offset });
+        }
+        let mut chunk = MapChunk { layers: [None, None, None] };
+        for layer in Layer::ALL {
+            if mask & (1 << layer as u8) == 0 {
+                continue;
+            }
+            let mut layer_slots = Vec::with_capacity(slots.min(PREALLOCATE_LIMIT));
+            for index in 0..slots {
+                layer_slots.push(reader.read_u32(MapField::ChunkSlot { x, y, layer, index })?);
+            }
+            chunk.layers[layer as usize] = Some(layer_slots);
+        }
+        Ok(chunk)
+    }
+}
+
+/// Write the map as the chunked map file.
+/// The chunks without any block are written as the empty chunk.
+pub fn write_chunked<W: Write>(map: &FixedUthMapData, chunk_width: u32, chunk_height: u32, mut writer: W) -> std::io::Result<()> {
+    if chunk_width == 0 || chunk_height == 0 {
+        return Err(std::io::Error::new(ErrorKind::InvalidInput, "Chunk size cannot be zero"));
+    }
+    let chunks_x = map.width.div_ceil(chunk_width);
+    let chunks_y = map.height.div_ceil(chunk_height);
+
+    let mut head = Vec::new();
+    head.write_all(b"uthc")?;
+    head.write_u16::<BE>(CHUNK_VERSION)?;
+    head.write_u32::<BE>(map.width)?;
+    head.write_u32::<BE>(map.height)?;
+    head.write_u32::<BE>(chunk_width)?;
+    head.write_u32::<BE>(chunk_height)?;
+    head.write_u32::<BE>(map.blocks.len().try_into().map_err(|_| std::io::Error::new(ErrorKind::InvalidInput, "Too many blocks"))?)?;
+    write_blocks(&mut head, &map.blocks)?;
+
+    let mut index = Vec::new();
+    let mut payloads = Vec::new();
+    let mut offset = head.len() as u64 + chunks_x as u64 * chunks_y as u64 * 12;
+    for cy in 0..chunks_y {
+        for cx in 0..chunks_x {
+            let mut payload = Vec::new();
+            let mut mask = 0u8;
+            let mut has_block = false;
+            for layer in Layer::ALL {
+                let slots = match map.get_layer(layer) {
+                    Some(slots) => slots,
+                    None => continue,
+                };
+                mask |= 1 << layer as u8;
+                for y in cy * chunk_height..(cy + 1) * chunk_height {
+                    for x in cx * chunk_width..(cx + 1) * chunk_width {
+                        let slot = if x < map.width && y < map.height {
+                            slots[y as usize * map.width as usize + x as usize]
+                        } else {
+                            0
+                        };
+                        has_block |= slot != 0;
+                        payload.write_u32::<BE>(slot)?;
+                    }
+                }
+            }
+            if !has_block {
+                index.push((offset, 0));
+                continue;
+            }
+            let len = u32::try_from(payload.len() + 1).map_err(|_| std::io::Error::new(ErrorKind::InvalidInput, "Chunk too large"))?;
+            index.push((offset, len));
+            offset += len as u64;
+            payloads.push((mask, payload));
+        }
+    }
+
+    writer.write_all(&head)?;
+    for (offset, len) in index {
+        writer.write_u64::<BE>(offset)?;
+        writer.write_u32::<BE>(len)?;
+    }
+    for (mask, payload) in payloads {
+        writer.write_u8(mask)?;
+        writer.write_all(&payload)?;
+    }
+    Ok(())
+}
+
+/// The chunked map with only the chunks around the camera loaded.
+/// The slots in the chunks not loaded are treated as empty.
+pub struct ChunkedMap {
+    header: Arc<ChunkedMapHeader>,
+    chunks: HashMap<(u32, u32), MapChunk>,
+}
+
+impl ChunkedMap {
+    pub fn new(header: Arc<ChunkedMapHeader>) -> Self {
+        Self {
+            header,
+            chunks: HashMap::new(),
+        }
+    }
+
+    pub fn get_header(&self) -> &Arc<ChunkedMapHeader> {
+        &self.header
+    }
+
+    pub fn insert_chunk(&mut self, chunk: (u32, u32), data: MapChunk) {
+        self.chunks.insert(chunk, data);
+    }
+
+    pub fn remove_chunk(&mut self, chunk: (u32, u32)) -> Option<MapChunk> {
+        self.chunks.remove(&chunk)
+    }
+
+    pub fn is_loaded(&self, chunk: (u32, u32)) -> bool {
+        self.chunks.contains_key(&chunk)
+    }
+
+    pub fn loaded_chunks(&self) -> impl Iterator<Item=(u32, u32)> + '_ {
+        self.chunks.keys().copied()
+    }
+
+    /// Get the block index of the layer in (x, y).
+    /// Return None if out of the map, the chunk is not loaded or the chunk does not have the layer.
+    pub fn get_layer_slot(&self, layer: Layer, x: usize, y: usize) -> Option<u32> {
+        let header = &self.header;
+        if x >= header.width as usize || y >= header.height as usize {
+            return None;
+        }
+        let (cw, ch) = (header.chunk_width as usize, header.chunk_height as usize);
+        let chunk = self.chunks.get(&((x / cw) as u32, (y / ch) as u32))?;
+        chunk.get_layer(layer)?.get((y % ch) * cw + x % cw).copied()
+    }
+}
+
+impl GameMap<BlockInfo> for ChunkedMap {
+    fn get_width(&self) -> Option<NonZeroUsize> {
+        NonZeroUsize::new(self.header.width as usize)
+    }
+
+    fn get_height(&self) -> Option<NonZeroUsize> {
+        NonZeroUsize::new(self.header.height as usize)
+    }
+
+    fn get_layer_block_info(&self, layer: Layer, x: usize, y: usize) -> Option<&BlockInfo> {
+        let idx = self.get_layer_slot(layer, x, y)?.checked_sub(1)?;
+        self.header.blocks.get(idx as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::bound::Bounding;
+
+    #[test]
+    fn round_trip() {
+        let blocks = vec![
+            BlockInfo::new("grass".into(), Vec::new(), HashMap::new(), Bounding::No),
+            BlockInfo::new("stone".into(), vec!["solid".into()], HashMap::from([("hp".to_string(), 2.5)]), Bounding::AllBlock),
+        ];
+        let terrain = vec![
+            1, 1, 2, 0, 1,
+            0, 2, 2, 1, 1,
+            1, 0, 0, 2, 0,
+        ];
+        let overlay = vec![
+            0, 0, 0, 0, 2,
+            1, 0, 0, 0, 0,
+            0, 0, 0, 0, 0,
+        ];
+        let mut map = FixedUthMapData::new(5, 3, blocks, terrain).unwrap();
+        map.set_layer(Layer::Overlay, overlay);
+        let mut bytes = Vec::new();
+        write_chunked(&map, 2, 2, &mut bytes).unwrap();
+
+        let header = ChunkedMapHeader::read_from(&bytes[..]).unwrap();
+        assert_eq!((header.get_size(), header.get_chunk_size(), header.get_chunk_count()), ((5, 3), (2, 2), (3, 2)));
+        let mut layers = [None, Some(vec![0; 15]), Some(vec![0; 15])];
+        for cy in 0..2 {
+            for cx in 0..3 {
+                let chunk = header.read_chunk(Cursor::new(&bytes), (cx, cy)).unwrap();
+                // the right bottom chunk has no block and is read as the empty chunk
+                let empty = (cx, cy) == (2, 1);
+                assert_eq!(chunk.get_layer(Layer::Overlay).is_none(), empty);
+                for layer in [Layer::Terrain, Layer::Overlay] {
+                    let Some(slots) = chunk.get_layer(layer) else { continue };
+                    assert_eq!(slots.len(), 4);
+                    for (i, &slot) in slots.iter().enumerate() {
+                        let (x, y) = (cx * 2 + i as u32 % 2, cy * 2 + i as u32 / 2);
+                        if x < 5 && y < 3 {
+                            layers[layer as usize].as_mut().unwrap()[y as usize * 5 + x as usize] = slot;
+                        } else {
+                            assert_eq!(slot, 0);
+                        }
+                    }
+                }
+            }
+        }
+        let [_, terrain, overlay] = layers;
+        let mut read = FixedUthMapData::new(5, 3, header.get_blocks().to_vec(), terrain.unwrap()).unwrap();
+        read.set_layer(Layer::Overlay, overlay.unwrap());
+        assert_eq!(read, map);
+    }
+
+    #[test]
+    fn huge_size_without_data() {
+        let mut bytes = b"uthc".to_vec();
+        bytes.extend_from_slice(&CHUNK_VERSION.to_be_bytes());
+        for value in [u32::MAX, u32::MAX, 1, 1, 0] {
+            bytes.extend_from_slice(&value.to_be_bytes());
+        }
+        assert!(matches!(ChunkedMapHeader::read_from(&bytes[..]), Err(MapParseError::Io { field: MapField::ChunkIndex { x: 0, y: 0 }, .. })));
+    }
+}
@@ -0,0 +1,104 @@
+//! The movement on the slot grid stopped by the bounding.
+//!
+//! The position is in slots from the left top, so the slot (0, 0) covers [0, 1) x [0, 1) and up is y - 1.
+//! The bounding is the same as the pathfinding, it blocks the movement towards its directions
+//! both leaving and entering the slot.
+
+use crate::bound::{Bounding, Direction};
+use crate::{GameBlock, GameMap};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Movement {
+    /// The position after the movement.
+    pub pos: (f32, f32),
+    /// The directions stopped by the bounding.
+    pub blocked: Bounding,
+}
+
+/// Move along one axis slot by slot until the target or the bounded edge.
+/// Return the new position on the axis and whether it is blocked.
+fn move_axis(start: f32, delta: f32, positive: Direction, negative: Direction, can_cross: impl Fn(isize, Direction) -> bool) -> (f32, bool) {
+    let target = start + delta;
+    let mut slot = start.floor() as isize;
+    if delta > 0.0 {
+        loop {
+            let edge = (slot + 1) as f32;
+            if target < edge {
+                return (target, false);
+            }
+            if !can_cross(slot, positive) {
+                // the slot of the position is floor of it, so it stops at the float right before the edge.
+                return (edge.next_down(), true);
+            }
+            slot += 1;
+        }
+    } else if delta < 0.0 {
+        loop {
+            let edge = slot as f32;
+            if target >= edge {
+                return (target, false);
+            }
+            if !can_cross(slot, negative) {
+                return (edge, true);
+            }
+            slot -= 1;
+        }
+    } else {
+        (start, false)
+    }
+}
+
+/// Move the point from the position by the velocity, the x axis first and then the y axis.
+/// The bounding function gives the bounding of the slot, including the slots out of the map.
+pub fn resolve_move(bounding: impl Fn(isize, isize) -> Bounding, pos: (f32, f32), velocity: (f32, f32)) -> Movement {
+    let can_cross = |x: isize, y: isize, to: Direction| {
+        let (dx, dy) = to.offset();
+        !bounding(x, y).check_bound(to) && !bounding(x + dx, y + dy).check_bound(to)
+    };
+    let slot_y = pos.1.floor() as isize;
+    let (x, blocked_x) = move_axis(pos.0, velocity.0, Direction::Right, Direction::Left, |x, to| can_cross(x, slot_y, to));
+    let slot_x = x.floor() as isize;
+    let (y, blocked_y) = move_axis(pos.1, velocity.1, Direction::Down, Direction::Up, |y, to| can_cross(slot_x, y, to));
+
+    let mut blocked = 0;
+    if blocked_x {
+        blocked |= if velocity.0 > 0.0 { Direction::Right } else { Direction::Left } as u8;
+    }
+    if blocked_y {
+        blocked |= if velocity.1 > 0.0 { Direction::Down } else { Direction::Up } as u8;
+    }
+    Movement {
+        pos: (x, y),
+        // only the bits of the directions are set.
+        blocked: Bounding::try_from(blocked).unwrap_or(Bounding::AllBlock),
+    }
+}
+
+/// Move in the terrain of the map by the block bounding, the outside of the map is all blocked.
+pub fn resolve_map_move<Block: GameBlock, M: GameMap<Block>>(map: &M, pos: (f32, f32), velocity: (f32, f32)) -> Movement {
+    let width = map.get_width().map_or(0, |x| x.get());
+    let height = map.get_height().map_or(0, |x| x.get());
+    resolve_move(|x, y| {
+        if x < 0 || y < 0 || x as usize >= width || y as usize >= height {
+            return Bounding::AllBlock;
+        }
+        map.get_block_info(x as usize, y as usize).map_or(Bounding::No, |b| b.get_bounding())
+    }, pos, velocity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stop_before_far_edge() {
+        let wall = |_: isize, y: isize| if y == 3000 { Bounding::AllBlock } else { Bounding::No };
+        let moved = resolve_move(wall, (0.5, 2999.5), (0.0, 2.0));
+        assert_eq!(moved.blocked, Bounding::Down);
+        assert!(moved.pos.1 < 3000.0);
+        assert_eq!(moved.pos.1.floor(), 2999.0);
+        let moved = resolve_move(wall, moved.pos, (0.0, 2.0));
+        assert_eq!(moved.blocked, Bounding::Down);
+        assert_eq!(moved.pos.1.floor(), 2999.0);
+    }
+}
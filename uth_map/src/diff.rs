@@ -0,0 +1,546 @@
+//! The difference between two maps of the same size, applicable as a patch onto another map.
+//!
+//! The blocks are compared by index, so removing a block in the middle shows as the following blocks changed.
+//! Every change keeps the value it is made from, applying the change onto the map with neither
+//! the old value nor the new value is a conflict.
+//!
+//! The text form of the diff:
+//!
+//! ```text
+//! uthdiff 1
+//! size 3 2
+//! resource 1 "grass" "stone"
+//! bounding 1 No AllBlock
+//! flag 1 + "solid"
+//! value 1 "hp" 3.5 -
+//! add-block 2 "water"
+//!     bounding No
+//! end
+//! add-layer background
+//! slot terrain 2 0 0 2
+//! remove-object "spawner" 1.5 0.5
+//! end
+//! ```
+//!
+//! `-` is for the value not present.
+//! `remove-layer` is followed by the rows of the removed slots as in the text form of the map.
+//! The equal objects are treated as one, adding the object already in the map changes nothing.
+
+use std::fmt::{Display, Formatter, Write};
+
+use crate::bound::Bounding;
+use crate::text::{layer_by_name, layer_name, write_properties, write_rows, TextParser};
+use crate::{BlockInfo, FixedUthMapData, Layer, MapObject, TextParseError};
+
+/// The version of the diff text form.
+const DIFF_VERSION: u32 = 1;
+
+/// One change of the map, the block index starts at 1.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MapChange {
+    Resource { block: u32, from: String, to: String },
+    Bounding { block: u32, from: Bounding, to: Bounding },
+    AddFlag { block: u32, flag: String },
+    RemoveFlag { block: u32, flag: String },
+    /// The value of the key, None if not present.
+    Value { block: u32, key: String, from: Option<f32>, to: Option<f32> },
+    /// The block is appended as the last block.
+    AddBlock { block: u32, info: BlockInfo },
+    /// The last block is removed.
+    RemoveBlock { block: u32, info: BlockInfo },
+    /// The layer is added with all slots empty.
+    AddLayer { layer: Layer },
+    /// The layer is removed, the slots are kept to check the conflict.
+    RemoveLayer { layer: Layer, slots: Vec<u32> },
+    Slot { layer: Layer, x: u32, y: u32, from: u32, to: u32 },
+    AddObject { object: MapObject },
+    RemoveObject { object: MapObject },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatchError {
+    /// The diff is only between the maps of the same size.
+    SizeMismatch {
+        expected: (u32, u32),
+        found: (u32, u32),
+    },
+    /// The map has neither the old value nor the new value of the change.
+    Conflict {
+        change: MapChange,
+    },
+}
+
+impl Display for PatchError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatchError::SizeMismatch { expected, found } => write!(f, "Map size {}x{} does not match {}x{}", found.0, found.1, expected.0, expected.1),
+            PatchError::Conflict { change } => write!(f, "Conflict on {:?}", change),
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+/// Compare the f32 by bits so NaN is the same as itself.
+fn same_value(a: Option<f32>, b: Option<f32>) -> bool {
+    a.map(f32::to_bits) == b.map(f32::to_bits)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapDiff {
+    width: u32,
+    height: u32,
+    changes: Vec<MapChange>,
+}
+
+fn diff_block(block: u32, old: &BlockInfo, new: &BlockInfo, changes: &mut Vec<MapChange>) {
+    if old.res != new.res {
+        changes.push(MapChange::Resource { block, from: old.res.clone(), to: new.res.clone() });
+    }
+    if old.bounding != new.bounding {
+        changes.push(MapChange::Bounding { block, from: old.bounding, to: new.bounding });
+    }
+    for flag in new.flags.iter().filter(|x| !old.flags.contains(x)) {
+        changes.push(MapChange::AddFlag { block, flag: flag.clone() });
+    }
+    for flag in old.flags.iter().filter(|x| !new.flags.contains(x)) {
+        changes.push(MapChange::RemoveFlag { block, flag: flag.clone() });
+    }
+    let mut keys = old.value.keys().chain(new.value.keys()).collect::<Vec<_>>();
+    keys.sort_unstable();
+    keys.dedup();
+    for key in keys {
+        let (from, to) = (old.value.get(key).copied(), new.value.get(key).copied());
+        if !same_value(from, to) {
+            changes.push(MapChange::Value { block, key: key.clone(), from, to });
+        }
+    }
+}
+
+impl MapDiff {
+    /// Get the changes from `old` to `new`.
+    pub fn between(old: &FixedUthMapData, new: &FixedUthMapData) -> Result<Self, PatchError> {
+        if (old.width, old.height) != (new.width, new.height) {
+            return Err(PatchError::SizeMismatch { expected: (old.width, old.height), found: (new.width, new.height) });
+        }
+        let mut changes = Vec::new();
+        for (i, (o, n)) in old.blocks.iter().zip(&new.blocks).enumerate() {
+            diff_block(i as u32 + 1, o, n, &mut changes);
+        }
+        for (i, info) in old.blocks.iter().enumerate().skip(new.blocks.len()).rev() {
+            changes.push(MapChange::RemoveBlock { block: i as u32 + 1, info: info.clone() });
+        }
+        for (i, info) in new.blocks.iter().enumerate().skip(old.blocks.len()) {
+            changes.push(MapChange::AddBlock { block: i as u32 + 1, info: info.clone() });
+        }
+
+        let empty = vec![0; old.map.len()];
+        for layer in Layer::ALL {
+            let new_slots = match new.get_layer(layer) {
+                Some(slots) => slots,
+                None => {
+                    if let Some(slots) = old.get_layer(layer) {
+                        changes.push(MapChange::RemoveLayer { layer, slots: slots.to_vec() });
+                    }
+                    continue;
+                }
+            };
+            let old_slots = match old.get_layer(layer) {
+                Some(slots) => slots,
+                None => {
+                    changes.push(MapChange::AddLayer { layer });
+                    &empty
+                }
+            };
+            for (i, (&from, &to)) in old_slots.iter().zip(new_slots).enumerate() {
+                if from != to {
+                    let (x, y) = (i % old.width as usize, i / old.width as usize);
+                    changes.push(MapChange::Slot { layer, x: x as u32, y: y as u32, from, to });
+                }
+            }
+        }
+
+        let mut added = new.objects.iter().map(Some).collect::<Vec<_>>();
+        for object in &old.objects {
+            match added.iter_mut().find(|x| **x == Some(object)) {
+                Some(x) => *x = None,
+                None => changes.push(MapChange::RemoveObject { object: object.clone() }),
+            }
+        }
+        changes.extend(added.into_iter().flatten().map(|object| MapChange::AddObject { object: object.clone() }));
+
+        Ok(Self {
+            width: old.width,
+            height: old.height,
+            changes,
+        })
+    }
+
+    pub fn get_size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn get_changes(&self) -> &[MapChange] {
+        &self.changes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Apply the changes onto the map.
+    /// The changes already in the map are skipped, and the map is unchanged if any change conflicts.
+    pub fn apply(&self, map: &mut FixedUthMapData) -> Result<(), PatchError> {
+        if (map.width, map.height) != (self.width, self.height) {
+            return Err(PatchError::SizeMismatch { expected: (self.width, self.height), found: (map.width, map.height) });
+        }
+        let mut patched = map.clone();
+        for change in &self.changes {
+            if !apply_change(&mut patched, change) {
+                return Err(PatchError::Conflict { change: change.clone() });
+            }
+        }
+        *map = patched;
+        Ok(())
+    }
+}
+
+fn layer_mut(map: &mut FixedUthMapData, layer: Layer) -> Option<&mut Vec<u32>> {
+    match layer {
+        Layer::Background => map.background.as_mut(),
+        Layer::Terrain => Some(&mut map.map),
+        Layer::Overlay => map.overlay.as_mut(),
+    }
+}
+
+fn block_mut(map: &mut FixedUthMapData, block: u32) -> Option<&mut BlockInfo> {
+    map.blocks.get_mut((block as usize).checked_sub(1)?)
+}
+
+/// Apply one change, return false if conflict.
+fn apply_change(map: &mut FixedUthMapData, change: &MapChange) -> bool {
+    match change {
+        MapChange::Resource { block, from, to } => match block_mut(map, *block) {
+            Some(info) if info.res == *to => true,
+            Some(info) if info.res == *from => {
+                info.res = to.clone();
+                true
+            }
+            _ => false,
+        },
+        MapChange::Bounding { block, from, to } => match block_mut(map, *block) {
+            Some(info) if info.bounding == *to => true,
+            Some(info) if info.bounding == *from => {
+                info.bounding = *to;
+                true
+            }
+            _ => false,
+        },
+        MapChange::AddFlag { block, flag } => match block_mut(map, *block) {
+            Some(info) => {
+                if !info.flags.contains(flag) {
+                    info.flags.push(flag.clone());
+                }
+                true
+            }
+            None => false,
+        },
+        MapChange::RemoveFlag { block, flag } => match block_mut(map, *block) {
+            Some(info) => {
+                info.flags.retain(|x| x != flag);
+                true
+            }
+            None => false,
+        },
+        MapChange::Value { block, key, from, to } => match block_mut(map, *block) {
+            Some(info) => {
+                let current = info.value.get(key).copied();
+                if same_value(current, *to) {
+                    return true;
+                }
+                if !same_value(current, *from) {
+                    return false;
+                }
+                match to {
+                    Some(v) => info.value.insert(key.clone(), *v),
+                    None => info.value.remove(key),
+                };
+                true
+            }
+            None => false,
+        },
+        MapChange::AddBlock { block, info } => {
+            if (*block as usize).checked_sub(1).and_then(|i| map.blocks.get(i)) == Some(info) {
+                return true;
+            }
+            if map.blocks.len() + 1 != *block as usize {
+                return false;
+            }
+            map.blocks.push(info.clone());
+            true
+        }
+        MapChange::RemoveBlock { block, info } => {
+            if map.blocks.len() < *block as usize {
+                return true;
+            }
+            if map.blocks.len() != *block as usize || map.blocks.last() != Some(info) {
+                return false;
+            }
+            map.blocks.pop();
+            map.duplicate_keys.retain(|(block, _)| *block as usize != map.blocks.len());
+            true
+        }
+        MapChange::AddLayer { layer } => {
+            if map.get_layer(*layer).is_none() {
+                map.set_layer(*layer, vec![0; map.map.len()]);
+            }
+            true
+        }
+        MapChange::RemoveLayer { layer, slots } => match map.get_layer(*layer) {
+            None => true,
+            Some(current) if *layer != Layer::Terrain && current == &slots[..] => {
+                map.remove_layer(*layer);
+                true
+            }
+            _ => false,
+        },
+        MapChange::Slot { layer, x, y, from, to } => {
+            let width = map.width as usize;
+            if *x >= map.width || *y >= map.height {
+                return false;
+            }
+            match layer_mut(map, *layer).map(|slots| &mut slots[*y as usize * width + *x as usize]) {
+                Some(slot) if *slot == *to => true,
+                Some(slot) if *slot == *from => {
+                    *slot = *to;
+                    true
+                }
+                _ => false,
+            }
+        }
+        MapChange::AddObject { object } => {
+            if !map.objects.contains(object) {
+                map.objects.push(object.clone());
+            }
+            true
+        }
+        MapChange::RemoveObject { object } => {
+            if let Some(i) = map.objects.iter().position(|x| x == object) {
+                map.remove_object(i);
+            }
+            true
+        }
+    }
+}
+
+fn value_text(value: Option<f32>) -> String {
+    value.map_or("-".to_string(), |x| format!("{:?}", x))
+}
+
+fn write_block(out: &mut String, head: &str, block: u32, info: &BlockInfo) {
+    let _ = writeln!(out, "{} {} {:?}", head, block, info.res);
+    let _ = writeln!(out, "    bounding {:?}", info.bounding);
+    write_properties(out, &info.flags, &info.value);
+    out.push_str("end\n");
+}
+
+fn write_object(out: &mut String, head: &str, object: &MapObject) {
+    let (x, y) = object.get_pos();
+    let _ = writeln!(out, "{} {:?} {:?} {:?}", head, object.get_kind(), x, y);
+    write_properties(out, object.get_flags(), object.get_values());
+    out.push_str("end\n");
+}
+
+impl MapDiff {
+    /// Write the diff in the text form.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "uthdiff {}", DIFF_VERSION);
+        let _ = writeln!(out, "size {} {}", self.width, self.height);
+        for change in &self.changes {
+            match change {
+                MapChange::Resource { block, from, to } => {
+                    let _ = writeln!(out, "resource {} {:?} {:?}", block, from, to);
+                }
+                MapChange::Bounding { block, from, to } => {
+                    let _ = writeln!(out, "bounding {} {:?} {:?}", block, from, to);
+                }
+                MapChange::AddFlag { block, flag } => {
+                    let _ = writeln!(out, "flag {} + {:?}", block, flag);
+                }
+                MapChange::RemoveFlag { block, flag } => {
+                    let _ = writeln!(out, "flag {} - {:?}", block, flag);
+                }
+                MapChange::Value { block, key, from, to } => {
+                    let _ = writeln!(out, "value {} {:?} {} {}", block, key, value_text(*from), value_text(*to));
+                }
+                MapChange::AddBlock { block, info } => write_block(&mut out, "add-block", *block, info),
+                MapChange::RemoveBlock { block, info } => write_block(&mut out, "remove-block", *block, info),
+                MapChange::AddLayer { layer } => {
+                    let _ = writeln!(out, "add-layer {}", layer_name(*layer));
+                }
+                MapChange::RemoveLayer { layer, slots } => {
+                    let _ = writeln!(out, "remove-layer {}", layer_name(*layer));
+                    write_rows(&mut out, slots, self.width);
+                }
+                MapChange::Slot { layer, x, y, from, to } => {
+                    let _ = writeln!(out, "slot {} {} {} {} {}", layer_name(*layer), x, y, from, to);
+                }
+                MapChange::AddObject { object } => write_object(&mut out, "add-object", object),
+                MapChange::RemoveObject { object } => write_object(&mut out, "remove-object", object),
+            }
+        }
+        out
+    }
+
+    /// Parse the diff from the text form.
+    pub fn from_text(text: &str) -> Result<Self, TextParseError> {
+        let mut parser = TextParser::new(text);
+        let tokens = parser.expect_tokens("header")?;
+        if tokens[0] != "uthdiff" {
+            return parser.err("Not uth map diff");
+        }
+        let version: u32 = parser.number(&parser.args(&tokens, 1)?[0])?;
+        if version != DIFF_VERSION {
+            return parser.err(format!("Unsupported diff version {}", version));
+        }
+        let tokens = parser.expect_tokens("size")?;
+        if tokens[0] != "size" {
+            return parser.err("Expect size");
+        }
+        let args = parser.args(&tokens, 2)?;
+        let (width, height) = (parser.number(&args[0])?, parser.number(&args[1])?);
+
+        let value = |parser: &TextParser, token: &str| -> Result<Option<f32>, TextParseError> {
+            if token == "-" {
+                Ok(None)
+            } else {
+                parser.number(token).map(Some)
+            }
+        };
+        let layer = |parser: &TextParser, token: &str| match layer_by_name(token) {
+            Some(layer) => Ok(layer),
+            None => parser.err(format!("Unknown layer {:?}", token)),
+        };
+        let bounding = |parser: &TextParser, token: &str| match crate::text::bounding_by_name(token) {
+            Some(b) => Ok(b),
+            None => parser.err(format!("Unknown bounding {:?}", token)),
+        };
+
+        let mut changes = Vec::new();
+        while let Some(tokens) = parser.next_tokens()? {
+            changes.push(match tokens[0].as_str() {
+                "resource" => {
+                    let args = parser.args(&tokens, 3)?;
+                    MapChange::Resource { block: parser.number(&args[0])?, from: args[1].clone(), to: args[2].clone() }
+                }
+                "bounding" => {
+                    let args = parser.args(&tokens, 3)?;
+                    MapChange::Bounding { block: parser.number(&args[0])?, from: bounding(&parser, &args[1])?, to: bounding(&parser, &args[2])? }
+                }
+                "flag" => {
+                    let args = parser.args(&tokens, 3)?;
+                    let (block, flag) = (parser.number(&args[0])?, args[2].clone());
+                    match args[1].as_str() {
+                        "+" => MapChange::AddFlag { block, flag },
+                        "-" => MapChange::RemoveFlag { block, flag },
+                        other => return parser.err(format!("Expect + or -, found {:?}", other)),
+                    }
+                }
+                "value" => {
+                    let args = parser.args(&tokens, 4)?;
+                    MapChange::Value { block: parser.number(&args[0])?, key: args[1].clone(), from: value(&parser, &args[2])?, to: value(&parser, &args[3])? }
+                }
+                "add-block" | "remove-block" => {
+                    let args = parser.args(&tokens, 2)?;
+                    let (block, res) = (parser.number(&args[0])?, args[1].clone());
+                    let body = parser.parse_body(true)?;
+                    let info = BlockInfo::new(res, body.flags, body.value, body.bounding.unwrap_or(Bounding::No));
+                    if tokens[0] == "add-block" {
+                        MapChange::AddBlock { block, info }
+                    } else {
+                        MapChange::RemoveBlock { block, info }
+                    }
+                }
+                "add-layer" => MapChange::AddLayer { layer: layer(&parser, &parser.args(&tokens, 1)?[0])? },
+                "remove-layer" => {
+                    let layer = layer(&parser, &parser.args(&tokens, 1)?[0])?;
+                    MapChange::RemoveLayer { layer, slots: parser.parse_rows(width, height)? }
+                }
+                "slot" => {
+                    let args = parser.args(&tokens, 5)?;
+                    MapChange::Slot {
+                        layer: layer(&parser, &args[0])?,
+                        x: parser.number(&args[1])?,
+                        y: parser.number(&args[2])?,
+                        from: parser.number(&args[3])?,
+                        to: parser.number(&args[4])?,
+                    }
+                }
+                "add-object" | "remove-object" => {
+                    let args = parser.args(&tokens, 3)?;
+                    let (kind, x, y) = (args[0].clone(), parser.number(&args[1])?, parser.number(&args[2])?);
+                    let body = parser.parse_body(false)?;
+                    let object = MapObject::new(kind, x, y, body.flags, body.value);
+                    if tokens[0] == "add-object" {
+                        MapChange::AddObject { object }
+                    } else {
+                        MapChange::RemoveObject { object }
+                    }
+                }
+                other => return parser.err(format!("Unknown change {:?}", other)),
+            });
+        }
+        Ok(Self {
+            width,
+            height,
+            changes,
+        })
+    }
+}
+
+impl FixedUthMapData {
+    /// Get the changes from this map to the other map, see [`MapDiff::between`].
+    pub fn diff(&self, other: &FixedUthMapData) -> Result<MapDiff, PatchError> {
+        MapDiff::between(self, other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map_with_background(background: Vec<u32>) -> FixedUthMapData {
+        let mut map = FixedUthMapData::new(2, 1, Vec::new(), vec![0, 0]).unwrap();
+        map.set_layer(Layer::Background, background);
+        map
+    }
+
+    #[test]
+    fn remove_changed_layer_conflicts() {
+        let base = map_with_background(vec![0, 0]);
+        let mine = FixedUthMapData::new(2, 1, Vec::new(), vec![0, 0]).unwrap();
+        let diff = MapDiff::between(&base, &mine).unwrap();
+        assert_eq!(MapDiff::from_text(&diff.to_text()), Ok(diff.clone()));
+
+        let mut theirs = map_with_background(vec![5, 5]);
+        assert!(matches!(diff.apply(&mut theirs), Err(PatchError::Conflict { .. })));
+        assert_eq!(theirs.get_layer(Layer::Background), Some(&[5, 5][..]));
+
+        let mut same = base.clone();
+        diff.apply(&mut same).unwrap();
+        assert_eq!(same, mine);
+    }
+
+    #[test]
+    fn apply_twice() {
+        let base = FixedUthMapData::new(2, 1, Vec::new(), vec![0, 0]).unwrap();
+        let mut mine = base.clone();
+        mine.objects.push(MapObject::new("spawner".into(), 1.5, 0.5, Vec::new(), Default::default()));
+        let diff = MapDiff::between(&base, &mine).unwrap();
+        let mut patched = base.clone();
+        diff.apply(&mut patched).unwrap();
+        diff.apply(&mut patched).unwrap();
+        assert_eq!(patched, mine);
+    }
+}
@@ -0,0 +1,175 @@
+use std::fmt::{Display, Formatter};
+
+use crate::Layer;
+
+/// The field that was being read when the parsing failed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MapField {
+    Header,
+    Version,
+    /// The flags in the header since version 2.
+    Flags,
+    BlockCount,
+    Width,
+    Height,
+    FlagCount { block: u32 },
+    KeyValueCount { block: u32 },
+    Bounding { block: u32 },
+    Resource { block: u32 },
+    Flag { block: u32, index: u8 },
+    Key { block: u32, index: u8 },
+    Value { block: u32, index: u8 },
+    /// The slot index in the grid.
+    Slot { index: usize },
+    SectionCount,
+    SectionTag { index: u32 },
+    SectionLength { index: u32 },
+    Section { tag: [u8; 4] },
+    Layer,
+    LayerSlot { layer: Layer, index: usize },
+    ObjectCount,
+    ObjectFlagCount { object: u32 },
+    ObjectKeyValueCount { object: u32 },
+    ObjectX { object: u32 },
+    ObjectY { object: u32 },
+    ObjectKind { object: u32 },
+    ObjectFlag { object: u32, index: u8 },
+    ObjectKey { object: u32, index: u8 },
+    ObjectValue { object: u32, index: u8 },
+    ChunkWidth,
+    ChunkHeight,
+    /// The index entry of the chunk in the chunked map.
+    ChunkIndex { x: u32, y: u32 },
+    ChunkLayers { x: u32, y: u32 },
+    ChunkSlot { x: u32, y: u32, layer: Layer, index: usize },
+}
+
+#[derive(Debug)]
+pub enum MapParseError {
+    /// The file does not start with the uth header.
+    NotMapFile,
+    /// The file is written by a newer version.
+    UnsupportedVersion {
+        version: u16,
+    },
+    /// Failed to read the field, usually the file ends too early.
+    Io {
+        field: MapField,
+        offset: u64,
+        source: std::io::Error,
+    },
+    /// The zero-end string is not valid utf8.
+    InvalidUtf8 {
+        field: MapField,
+        offset: u64,
+    },
+    /// The bounding byte has bits other than the four directions.
+    InvalidBounding {
+        block: u32,
+        value: u8,
+        offset: u64,
+    },
+    /// The layer id is unknown or is the terrain which is not stored in the sections.
+    InvalidLayer {
+        value: u8,
+        offset: u64,
+    },
+    /// The header has the flags not known by this version.
+    UnsupportedFlags {
+        flags: u8,
+    },
+    /// The varint is longer than 5 bytes or larger than u32.
+    InvalidVarint {
+        field: MapField,
+        offset: u64,
+    },
+    /// The run of the slots is empty, longer than the slots left or cannot be held in memory.
+    InvalidRun {
+        field: MapField,
+        offset: u64,
+    },
+    /// The known section has more content than its length.
+    SectionOverrun {
+        tag: [u8; 4],
+        offset: u64,
+    },
+    /// `width * height` cannot be held in memory.
+    SizeOverflow {
+        width: u32,
+        height: u32,
+    },
+    /// The chunk is out of the chunked map.
+    NoSuchChunk {
+        x: u32,
+        y: u32,
+    },
+}
+
+impl Display for MapField {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MapField::Header => write!(f, "header"),
+            MapField::Version => write!(f, "version"),
+            MapField::Flags => write!(f, "flags"),
+            MapField::BlockCount => write!(f, "block count"),
+            MapField::Width => write!(f, "width"),
+            MapField::Height => write!(f, "height"),
+            MapField::FlagCount { block } => write!(f, "flag count of block {}", block),
+            MapField::KeyValueCount { block } => write!(f, "key value count of block {}", block),
+            MapField::Bounding { block } => write!(f, "bounding of block {}", block),
+            MapField::Resource { block } => write!(f, "resource id of block {}", block),
+            MapField::Flag { block, index } => write!(f, "flag {} of block {}", index, block),
+            MapField::Key { block, index } => write!(f, "key {} of block {}", index, block),
+            MapField::Value { block, index } => write!(f, "value {} of block {}", index, block),
+            MapField::Slot { index } => write!(f, "slot {}", index),
+            MapField::SectionCount => write!(f, "section count"),
+            MapField::SectionTag { index } => write!(f, "tag of section {}", index),
+            MapField::SectionLength { index } => write!(f, "length of section {}", index),
+            MapField::Section { tag } => write!(f, "section {}", String::from_utf8_lossy(tag)),
+            MapField::Layer => write!(f, "layer"),
+            MapField::LayerSlot { layer, index } => write!(f, "slot {} of layer {:?}", index, layer),
+            MapField::ObjectCount => write!(f, "object count"),
+            MapField::ObjectFlagCount { object } => write!(f, "flag count of object {}", object),
+            MapField::ObjectKeyValueCount { object } => write!(f, "key value count of object {}", object),
+            MapField::ObjectX { object } => write!(f, "x of object {}", object),
+            MapField::ObjectY { object } => write!(f, "y of object {}", object),
+            MapField::ObjectKind { object } => write!(f, "type id of object {}", object),
+            MapField::ObjectFlag { object, index } => write!(f, "flag {} of object {}", index, object),
+            MapField::ObjectKey { object, index } => write!(f, "key {} of object {}", index, object),
+            MapField::ObjectValue { object, index } => write!(f, "value {} of object {}", index, object),
+            MapField::ChunkWidth => write!(f, "chunk width"),
+            MapField::ChunkHeight => write!(f, "chunk height"),
+            MapField::ChunkIndex { x, y } => write!(f, "index of chunk ({}, {})", x, y),
+            MapField::ChunkLayers { x, y } => write!(f, "layers of chunk ({}, {})", x, y),
+            MapField::ChunkSlot { x, y, layer, index } => write!(f, "slot {} of layer {:?} in chunk ({}, {})", index, layer, x, y),
+        }
+    }
+}
+
+impl Display for MapParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MapParseError::NotMapFile => write!(f, "Not map file"),
+            MapParseError::UnsupportedVersion { version } => write!(f, "Unsupported map version {}", version),
+            MapParseError::Io { field, offset, source } => write!(f, "Read {} failed at byte {}: {}", field, offset, source),
+            MapParseError::InvalidUtf8 { field, offset } => write!(f, "Invalid utf8 string for {} at byte {}", field, offset),
+            MapParseError::InvalidBounding { block, value, offset } => write!(f, "Invalid bounding {:#010b} of block {} at byte {}", value, block, offset),
+            MapParseError::InvalidLayer { value, offset } => write!(f, "Invalid layer {} at byte {}", value, offset),
+            MapParseError::UnsupportedFlags { flags } => write!(f, "Unsupported map flags {:#010b}", flags),
+            MapParseError::InvalidVarint { field, offset } => write!(f, "Invalid varint for {} at byte {}", field, offset),
+            MapParseError::InvalidRun { field, offset } => write!(f, "Invalid run for {} at byte {}", field, offset),
+            MapParseError::SectionOverrun { tag, offset } => write!(f, "Section {} starts at byte {} is longer than its length", String::from_utf8_lossy(tag), offset),
+            MapParseError::SizeOverflow { width, height } => write!(f, "Map size {}x{} overflow", width, height),
+            MapParseError::NoSuchChunk { x, y } => write!(f, "No chunk ({}, {}) in the map", x, y),
+        }
+    }
+}
+
+impl std::error::Error for MapParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MapParseError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
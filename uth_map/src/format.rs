@@ -0,0 +1,514 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, ErrorKind, Read, Write};
+
+use byteorder::{WriteBytesExt, BE};
+
+use crate::bound::Bounding;
+use crate::error::{MapField, MapParseError};
+use crate::read::{MapReader, PREALLOCATE_LIMIT};
+use crate::{BlockInfo, FixedUthMapData, Layer, MapObject};
+
+/// The newest version read and written.
+/// The maps with the raw slots are still written in version 1 for the older readers.
+pub const MAP_VERSION: u16 = 2;
+
+/// The most slots of a run-length encoded map.
+/// A few bytes of runs can fill any size, so the larger maps are rejected as [`MapParseError::SizeOverflow`]
+/// instead of allocating as much as the header claims.
+pub const MAX_RUN_LENGTH_SLOTS: usize = 1 << 24;
+
+/// The flag in the version 2 header, the slot grids are run-length encoded.
+const FLAG_RUN_LENGTH: u8 = 0b0000_0001;
+
+/// The byte after the "uth" magic in versioned files.
+/// Legacy files have the block count there, which would need at least 0xFF000000 blocks to start with this.
+const VERSION_MARKER: u8 = 0xFF;
+
+/// The section holds the slots of the background or overlay layer.
+const LAYER_TAG: &[u8; 4] = b"LAYR";
+/// The section holds the free-positioned objects.
+const OBJECT_TAG: &[u8; 4] = b"OBJS";
+
+/// How the slot grids of the terrain and the layers are stored.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum SlotEncoding {
+    /// u32 be per slot.
+    #[default]
+    Raw,
+    /// The runs of the same block, the run length and the block index both in varint.
+    RunLength,
+}
+
+/// Read the slot grid of `slots` slots.
+fn read_slots<R: BufRead>(reader: &mut MapReader<R>, slots: usize, encoding: SlotEncoding,
+                          field: impl Fn(usize) -> MapField) -> Result<Vec<u32>, MapParseError> {
+    let mut map = Vec::with_capacity(slots.min(PREALLOCATE_LIMIT));
+    match encoding {
+        SlotEncoding::Raw => {
+            for index in 0..slots {
+                map.push(reader.read_u32(field(index))?);
+            }
+        }
+        SlotEncoding::RunLength => {
+            while map.len() < slots {
+                let index = map.len();
+                let offset = reader.offset();
+                let run = reader.read_varint(field(index))? as usize;
+                let block = reader.read_varint(field(index))?;
+                if run == 0 || run > slots - index || map.try_reserve(run).is_err() {
+                    return Err(MapParseError::InvalidRun { field: field(index), offset });
+                }
+                map.resize(index + run, block);
+            }
+        }
+    }
+    Ok(map)
+}
+
+fn write_varint<W: Write>(writer: &mut W, mut value: u32) -> std::io::Result<()> {
+    while value >= 0x80 {
+        writer.write_u8(value as u8 | 0x80)?;
+        value >>= 7;
+    }
+    writer.write_u8(value as u8)
+}
+
+fn write_slots<W: Write>(writer: &mut W, slots: &[u32], encoding: SlotEncoding) -> std::io::Result<()> {
+    match encoding {
+        SlotEncoding::Raw => {
+            for block_idx in slots {
+                writer.write_u32::<BE>(*block_idx)?;
+            }
+        }
+        SlotEncoding::RunLength => {
+            for run in slots.chunk_by(|a, b| a == b).flat_map(|x| x.chunks(u32::MAX as usize)) {
+                write_varint(writer, run.len() as u32)?;
+                write_varint(writer, run[0])?;
+            }
+        }
+    }
+    Ok(())
+}
+
+struct Properties {
+    flags: Vec<String>,
+    value: HashMap<String, f32>,
+    /// The keys appeared more than once.
+    duplicate_keys: Vec<String>,
+}
+
+/// Read the flags and the key values following the counts.
+fn read_properties<R: BufRead>(reader: &mut MapReader<R>,
+                               flag_count: u8,
+                               key_value_count: u8,
+                               flag_field: impl Fn(u8) -> MapField,
+                               key_field: impl Fn(u8) -> MapField,
+                               value_field: impl Fn(u8) -> MapField) -> Result<Properties, MapParseError> {
+    let mut flags = Vec::with_capacity(flag_count as _);
+    for index in 0..flag_count {
+        flags.push(reader.read_zero_end_string(flag_field(index))?);
+    }
+    let mut key_values = HashMap::with_capacity(key_value_count as _);
+    let mut duplicate_keys = Vec::new();
+    for index in 0..key_value_count {
+        let key = reader.read_zero_end_string(key_field(index))?;
+        let value = reader.read_f32(value_field(index))?;
+        if key_values.contains_key(&key) {
+            duplicate_keys.push(key.clone());
+        }
+        key_values.insert(key, value);
+    }
+    Ok(Properties {
+        flags,
+        value: key_values,
+        duplicate_keys,
+    })
+}
+
+/// Read the blocks in the block table, the duplicate keys in them are pushed with the block index.
+pub(crate) fn read_blocks<R: BufRead>(reader: &mut MapReader<R>, blocks: u32, duplicate_keys: &mut Vec<(u32, String)>) -> Result<Vec<BlockInfo>, MapParseError> {
+    let mut block_info = Vec::with_capacity((blocks as usize).min(PREALLOCATE_LIMIT));
+
+    for block in 0..blocks {
+        let flag_count = reader.read_u8(MapField::FlagCount { block })?;
+        let key_value_count = reader.read_u8(MapField::KeyValueCount { block })?;
+        let offset = reader.offset();
+        let bounding = reader.read_u8(MapField::Bounding { block })?;
+        let bounding = Bounding::try_from(bounding).map_err(|value| MapParseError::InvalidBounding { block, value, offset })?;
+        let res = reader.read_zero_end_string(MapField::Resource { block })?;
+
+        let properties = read_properties(reader, flag_count, key_value_count,
+                                         |index| MapField::Flag { block, index },
+                                         |index| MapField::Key { block, index },
+                                         |index| MapField::Value { block, index })?;
+        duplicate_keys.extend(properties.duplicate_keys.into_iter().map(|key| (block, key)));
+        block_info.push(BlockInfo {
+            res,
+            flags: properties.flags,
+            value: properties.value,
+            bounding,
+        })
+    }
+    Ok(block_info)
+}
+
+impl FixedUthMapData {
+    /// Parse the map from the buffered reader.
+    /// The header, block table and slot grid are read in order without buffering the whole file.
+    fn parse<R: BufRead>(reader: &mut MapReader<R>) -> Result<Self, MapParseError> {
+        let mut header = [0; 3];
+        if reader.read_exact(&mut header, MapField::Header).is_err() || &header != b"uth" {
+            return Err(MapParseError::NotMapFile);
+        }
+        let version = if reader.peek_u8(MapField::Version)? == VERSION_MARKER {
+            reader.read_u8(MapField::Version)?;
+            reader.read_u16(MapField::Version)?
+        } else {
+            0
+        };
+        match version {
+            0 => Self::parse_v0(reader, SlotEncoding::Raw),
+            1 => Self::parse_v1(reader, SlotEncoding::Raw),
+            2 => {
+                let flags = reader.read_u8(MapField::Flags)?;
+                if flags & !FLAG_RUN_LENGTH != 0 {
+                    return Err(MapParseError::UnsupportedFlags { flags });
+                }
+                let encoding = if flags & FLAG_RUN_LENGTH != 0 { SlotEncoding::RunLength } else { SlotEncoding::Raw };
+                Self::parse_v1(reader, encoding)
+            }
+            _ => Err(MapParseError::UnsupportedVersion { version }),
+        }
+    }
+
+    /// The legacy file without version, only the block table and the slot grid.
+    /// Since version 2 the slot grid may be encoded by the header flags.
+    fn parse_v0<R: BufRead>(reader: &mut MapReader<R>, encoding: SlotEncoding) -> Result<Self, MapParseError> {
+        let blocks = reader.read_u32(MapField::BlockCount)?;
+        let width = reader.read_u32(MapField::Width)?;
+        let height = reader.read_u32(MapField::Height)?;
+
+        let mut duplicate_keys = Vec::new();
+        let block_info = read_blocks(reader, blocks, &mut duplicate_keys)?;
+        let slots = (width as usize).checked_mul(height as usize).ok_or(MapParseError::SizeOverflow { width, height })?;
+        if encoding == SlotEncoding::RunLength && slots > MAX_RUN_LENGTH_SLOTS {
+            return Err(MapParseError::SizeOverflow { width, height });
+        }
+        let map = read_slots(reader, slots, encoding, |index| MapField::Slot { index })?;
+
+        Ok(Self {
+            width,
+            height,
+            blocks: block_info,
+            map,
+            background: None,
+            overlay: None,
+            objects: Vec::new(),
+            duplicate_keys,
+            duplicate_object_keys: Vec::new(),
+        })
+    }
+
+    /// The v0 content followed by the sections.
+    /// Unknown sections are skipped so older readers can load the maps with new sections.
+    fn parse_v1<R: BufRead>(reader: &mut MapReader<R>, encoding: SlotEncoding) -> Result<Self, MapParseError> {
+        let mut map = Self::parse_v0(reader, encoding)?;
+        let sections = reader.read_u32(MapField::SectionCount)?;
+        for index in 0..sections {
+            let mut tag = [0; 4];
+            reader.read_exact(&mut tag, MapField::SectionTag { index })?;
+            let len = reader.read_u32(MapField::SectionLength { index })? as u64;
+            let offset = reader.offset();
+            match &tag {
+                LAYER_TAG => map.parse_layer_section(reader, encoding)?,
+                OBJECT_TAG => map.parse_object_section(reader)?,
+                _ => {}
+            }
+            // the newer writer may append data to the known sections.
+            let read = reader.offset() - offset;
+            if read > len {
+                return Err(MapParseError::SectionOverrun { tag, offset });
+            }
+            reader.skip(len - read, MapField::Section { tag })?;
+        }
+        Ok(map)
+    }
+
+    fn parse_layer_section<R: BufRead>(&mut self, reader: &mut MapReader<R>, encoding: SlotEncoding) -> Result<(), MapParseError> {
+        let offset = reader.offset();
+        let layer = reader.read_u8(MapField::Layer)?;
+        let layer = match Layer::try_from(layer) {
+            Ok(Layer::Terrain) | Err(_) => return Err(MapParseError::InvalidLayer { value: layer, offset }),
+            Ok(layer) => layer,
+        };
+        let slots = read_slots(reader, self.map.len(), encoding, |index| MapField::LayerSlot { layer, index })?;
+        self.set_layer(layer, slots);
+        Ok(())
+    }
+
+    fn parse_object_section<R: BufRead>(&mut self, reader: &mut MapReader<R>) -> Result<(), MapParseError> {
+        let objects = reader.read_u32(MapField::ObjectCount)?;
+        for object in 0..objects {
+            let flag_count = reader.read_u8(MapField::ObjectFlagCount { object })?;
+            let key_value_count = reader.read_u8(MapField::ObjectKeyValueCount { object })?;
+            let x = reader.read_f32(MapField::ObjectX { object })?;
+            let y = reader.read_f32(MapField::ObjectY { object })?;
+            let kind = reader.read_zero_end_string(MapField::ObjectKind { object })?;
+            let properties = read_properties(reader, flag_count, key_value_count,
+                                             |index| MapField::ObjectFlag { object, index },
+                                             |index| MapField::ObjectKey { object, index },
+                                             |index| MapField::ObjectValue { object, index })?;
+            self.duplicate_object_keys.extend(properties.duplicate_keys.into_iter().map(|key| (object, key)));
+            self.objects.push(MapObject::new(kind, x, y, properties.flags, properties.value));
+        }
+        Ok(())
+    }
+
+    /// Read the map from any reader.
+    /// The reader will be buffered internally.
+    pub fn read_from<R: Read>(reader: R) -> Result<Self, MapParseError> {
+        Self::parse(&mut MapReader::new(BufReader::new(reader)))
+    }
+
+    pub fn from_file(file: File) -> Result<Self, MapParseError> {
+        Self::read_from(file)
+    }
+}
+
+fn write_zero_end_string<W: Write>(writer: &mut W, s: &str) -> std::io::Result<()> {
+    if s.as_bytes().contains(&0) {
+        return Err(std::io::Error::new(ErrorKind::InvalidInput, "String contains zero byte"));
+    }
+    writer.write_all(s.as_bytes())?;
+    writer.write_all(&[0])
+}
+
+fn write_counts<W: Write>(writer: &mut W, flags: &[String], values: &HashMap<String, f32>) -> std::io::Result<()> {
+    let count_err = || std::io::Error::new(ErrorKind::InvalidInput, "Too many entries for u8 count");
+    writer.write_u8(flags.len().try_into().map_err(|_| count_err())?)?;
+    writer.write_u8(values.len().try_into().map_err(|_| count_err())?)
+}
+
+/// Write the flags and the key values in key order so the same map always produces the same bytes.
+fn write_properties<W: Write>(writer: &mut W, flags: &[String], values: &HashMap<String, f32>) -> std::io::Result<()> {
+    for flag in flags {
+        write_zero_end_string(writer, flag)?;
+    }
+    let mut key_values = values.iter().collect::<Vec<_>>();
+    key_values.sort_unstable_by(|a, b| a.0.cmp(b.0));
+    for (key, value) in key_values {
+        write_zero_end_string(writer, key)?;
+        writer.write_f32::<BE>(*value)?;
+    }
+    Ok(())
+}
+
+/// Write the blocks in the block table without the count.
+pub(crate) fn write_blocks<W: Write>(writer: &mut W, blocks: &[BlockInfo]) -> std::io::Result<()> {
+    for block in blocks {
+        write_counts(writer, &block.flags, &block.value)?;
+        writer.write_u8(block.bounding as u8)?;
+        write_zero_end_string(writer, &block.res)?;
+        write_properties(writer, &block.flags, &block.value)?;
+    }
+    Ok(())
+}
+
+fn write_section<W: Write>(writer: &mut W, tag: &[u8; 4], content: &[u8]) -> std::io::Result<()> {
+    let len = u32::try_from(content.len()).map_err(|_| std::io::Error::new(ErrorKind::InvalidInput, "Section too large"))?;
+    writer.write_all(tag)?;
+    writer.write_u32::<BE>(len)?;
+    writer.write_all(content)
+}
+
+impl FixedUthMapData {
+    /// Write the map in the uth format with the raw slots.
+    /// Key values are written in key order so the same map always produces the same bytes.
+    pub fn write_to<W: Write>(&self, writer: W) -> std::io::Result<()> {
+        self.write_encoded_to(writer, SlotEncoding::Raw)
+    }
+
+    /// Write the map with the slot grids in the encoding.
+    /// The raw slots are written in version 1 and the others in [`MAP_VERSION`].
+    /// The run-length encoding fails for the maps larger than [`MAX_RUN_LENGTH_SLOTS`].
+    pub fn write_encoded_to<W: Write>(&self, mut writer: W, encoding: SlotEncoding) -> std::io::Result<()> {
+        if encoding == SlotEncoding::RunLength && self.map.len() > MAX_RUN_LENGTH_SLOTS {
+            return Err(std::io::Error::new(ErrorKind::InvalidInput, "Too many slots for run-length encoding"));
+        }
+        writer.write_all(b"uth")?;
+        writer.write_u8(VERSION_MARKER)?;
+        match encoding {
+            SlotEncoding::Raw => writer.write_u16::<BE>(1)?,
+            SlotEncoding::RunLength => {
+                writer.write_u16::<BE>(MAP_VERSION)?;
+                writer.write_u8(FLAG_RUN_LENGTH)?;
+            }
+        }
+        writer.write_u32::<BE>(self.blocks.len().try_into().map_err(|_| std::io::Error::new(ErrorKind::InvalidInput, "Too many blocks"))?)?;
+        writer.write_u32::<BE>(self.width)?;
+        writer.write_u32::<BE>(self.height)?;
+
+        write_blocks(&mut writer, &self.blocks)?;
+        write_slots(&mut writer, &self.map, encoding)?;
+
+        let layers = [Layer::Background, Layer::Overlay].into_iter()
+            .filter_map(|layer| self.get_layer(layer).map(|slots| (layer, slots)))
+            .collect::<Vec<_>>();
+        let has_objects = !self.objects.is_empty();
+        writer.write_u32::<BE>(layers.len() as u32 + has_objects as u32)?;
+        for (layer, slots) in layers {
+            let mut content = vec![layer as u8];
+            write_slots(&mut content, slots, encoding)?;
+            write_section(&mut writer, LAYER_TAG, &content)?;
+        }
+        if has_objects {
+            let mut content = Vec::new();
+            content.write_u32::<BE>(self.objects.len().try_into().map_err(|_| std::io::Error::new(ErrorKind::InvalidInput, "Too many objects"))?)?;
+            for object in &self.objects {
+                let (x, y) = object.get_pos();
+                write_counts(&mut content, object.get_flags(), object.get_values())?;
+                content.write_f32::<BE>(x)?;
+                content.write_f32::<BE>(y)?;
+                write_zero_end_string(&mut content, object.get_kind())?;
+                write_properties(&mut content, object.get_flags(), object.get_values())?;
+            }
+            write_section(&mut writer, OBJECT_TAG, &content)?;
+        }
+        Ok(())
+    }
+
+    pub fn to_file(&self, file: File) -> std::io::Result<()> {
+        self.to_file_encoded(file, SlotEncoding::Raw)
+    }
+
+    pub fn to_file_encoded(&self, file: File, encoding: SlotEncoding) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(file);
+        self.write_encoded_to(&mut writer, encoding)?;
+        writer.flush()
+    }
+}
+
+impl TryFrom<&FixedUthMapData> for Vec<u8> {
+    type Error = std::io::Error;
+
+    fn try_from(value: &FixedUthMapData) -> Result<Self, Self::Error> {
+        let mut buf = Vec::new();
+        value.write_to(&mut buf)?;
+        Ok(buf)
+    }
+}
+
+impl TryFrom<Vec<u8>> for FixedUthMapData {
+    type Error = MapParseError;
+
+    fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
+        Self::parse(&mut MapReader::new(&value[..]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> FixedUthMapData {
+        let mut value = HashMap::new();
+        value.insert("hp".to_string(), 3.5);
+        value.insert("armor".to_string(), -1.0);
+        let blocks = vec![
+            BlockInfo::new("grass".into(), Vec::new(), HashMap::new(), Bounding::No),
+            BlockInfo::new("stone".into(), vec!["solid".into(), "dark".into()], value.clone(), Bounding::AllBlock),
+        ];
+        let mut map = FixedUthMapData::new(4, 3, blocks, vec![1, 1, 1, 2, 0, 0, 2, 2, 1, 1, 1, 1]).unwrap();
+        map.set_layer(Layer::Background, vec![2; 12]);
+        map.set_layer(Layer::Overlay, vec![0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 2]);
+        map.objects.push(MapObject::new("spawner".into(), 1.5, 0.5, vec!["boss".into()], value));
+        map.objects.push(MapObject::new("light".into(), -2.0, 3.25, Vec::new(), HashMap::new()));
+        map
+    }
+
+    fn encode(map: &FixedUthMapData, encoding: SlotEncoding) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        map.write_encoded_to(&mut bytes, encoding).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn round_trip() {
+        let plain = FixedUthMapData::new(3, 2, sample().blocks, vec![0, 1, 2, 2, 1, 0]).unwrap();
+        for map in [plain, sample()] {
+            for encoding in [SlotEncoding::Raw, SlotEncoding::RunLength] {
+                let bytes = encode(&map, encoding);
+                let read = FixedUthMapData::try_from(bytes.clone()).unwrap();
+                assert_eq!(read, map);
+                assert_eq!(encode(&read, encoding), bytes);
+            }
+        }
+    }
+
+    #[test]
+    fn raw_is_version_1() {
+        let bytes = encode(&sample(), SlotEncoding::Raw);
+        assert_eq!(&bytes[..6], &[b'u', b't', b'h', VERSION_MARKER, 0, 1]);
+        let bytes = encode(&sample(), SlotEncoding::RunLength);
+        assert_eq!(&bytes[..7], &[b'u', b't', b'h', VERSION_MARKER, 0, 2, FLAG_RUN_LENGTH]);
+    }
+
+    #[test]
+    fn huge_size_without_data() {
+        let mut bytes = b"uth".to_vec();
+        bytes.extend_from_slice(&[0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+        assert!(matches!(FixedUthMapData::try_from(bytes), Err(MapParseError::Io { field: MapField::Slot { index: 0 }, .. })));
+
+        let mut bytes = b"uth".to_vec();
+        bytes.extend_from_slice(&[0x7F, 0xFF, 0xFF, 0xFF, 0, 0, 0, 1, 0, 0, 0, 1]);
+        assert!(matches!(FixedUthMapData::try_from(bytes), Err(MapParseError::Io { field: MapField::FlagCount { block: 0 }, .. })));
+    }
+
+    /// The legacy header with two blocks and no slot, and the first block without properties.
+    fn two_blocks() -> Vec<u8> {
+        let mut bytes = b"uth".to_vec();
+        bytes.extend_from_slice(&[0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0]);
+        bytes.extend_from_slice(&[0, 0, Bounding::No as u8, b'a', 0]);
+        bytes
+    }
+
+    #[test]
+    fn invalid_bounding() {
+        let mut bytes = two_blocks();
+        bytes.extend_from_slice(&[0, 0, 0xF1, b'b', 0]);
+        assert!(matches!(FixedUthMapData::try_from(bytes), Err(MapParseError::InvalidBounding { block: 1, value: 0xF1, offset: 22 })));
+    }
+
+    #[test]
+    fn cut_in_key_values() {
+        let mut bytes = two_blocks();
+        bytes.extend_from_slice(&[0, 2, Bounding::No as u8, b'b', 0]);
+        bytes.extend_from_slice(b"hp\0");
+        bytes.extend_from_slice(&1.5f32.to_be_bytes());
+        bytes.extend_from_slice(b"ar");
+        match FixedUthMapData::try_from(bytes) {
+            Err(MapParseError::Io { field, offset, .. }) => assert_eq!((field, offset), (MapField::Key { block: 1, index: 1 }, 32)),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn huge_run() {
+        let mut bytes = b"uth".to_vec();
+        bytes.extend_from_slice(&[VERSION_MARKER, 0, 2, FLAG_RUN_LENGTH]);
+        bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0x40, 0, 0, 0, 0x40, 0]);
+        write_varint(&mut bytes, 1 << 28).unwrap();
+        write_varint(&mut bytes, 0).unwrap();
+        bytes.extend_from_slice(&[0, 0, 0, 0]);
+        assert!(matches!(FixedUthMapData::try_from(bytes), Err(MapParseError::SizeOverflow { width: 16384, height: 16384 })));
+    }
+
+    #[test]
+    fn huge_run_without_data() {
+        let mut bytes = b"uth".to_vec();
+        bytes.extend_from_slice(&[VERSION_MARKER, 0, 2, FLAG_RUN_LENGTH]);
+        bytes.extend_from_slice(&[0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+        assert!(FixedUthMapData::try_from(bytes).is_err());
+    }
+}
@@ -0,0 +1,28 @@
+/// The layers of the map, drawn from background to overlay.
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Layer {
+    /// The decorative tiles behind everything.
+    Background = 0,
+    /// The collidable tiles, the only layer in the maps without layers.
+    Terrain = 1,
+    /// The tiles drawn in front of the entities.
+    Overlay = 2,
+}
+
+impl Layer {
+    pub const ALL: [Layer; 3] = [Layer::Background, Layer::Terrain, Layer::Overlay];
+}
+
+impl TryFrom<u8> for Layer {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => Layer::Background,
+            1 => Layer::Terrain,
+            2 => Layer::Overlay,
+            _ => return Err(value),
+        })
+    }
+}
@@ -1,10 +1,29 @@
 extern crate core;
 
 use std::collections::HashMap;
-use std::fs::File;
 use std::num::NonZeroUsize;
 
+pub use error::*;
+pub use format::{SlotEncoding, MAP_VERSION, MAX_RUN_LENGTH_SLOTS};
+pub use layer::Layer;
+pub use object::MapObject;
+pub use text::TextParseError;
+pub use validate::*;
+
+use crate::bound::Bounding;
+
 pub mod bound;
+pub mod chunk;
+pub mod collide;
+pub mod diff;
+pub mod error;
+mod format;
+pub mod layer;
+pub mod object;
+pub mod path;
+mod read;
+pub mod text;
+pub mod validate;
 
 /// The game block in the file.
 /// Not the map in the gaming stage.
@@ -14,6 +33,8 @@ pub trait GameBlock {
     fn get_flags(&self) -> &[String];
 
     fn get_values(&self) -> &HashMap<String, f32>;
+
+    fn get_bounding(&self) -> Bounding;
 }
 
 /// The game map in the file.
@@ -23,13 +44,20 @@ pub trait GameMap<Block: GameBlock> {
 
     fn get_height(&self) -> Option<NonZeroUsize>;
 
-    fn get_block_info(&self, x: usize, y: usize) -> Option<&Block>;
+    /// Get the block in the terrain layer.
+    fn get_block_info(&self, x: usize, y: usize) -> Option<&Block> {
+        self.get_layer_block_info(Layer::Terrain, x, y)
+    }
+
+    fn get_layer_block_info(&self, layer: Layer, x: usize, y: usize) -> Option<&Block>;
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct BlockInfo {
     res: String,
     flags: Vec<String>,
     value: HashMap<String, f32>,
+    bounding: Bounding,
 }
 
 impl GameBlock for BlockInfo {
@@ -44,16 +72,51 @@ impl GameBlock for BlockInfo {
     fn get_values(&self) -> &HashMap<String, f32> {
         &self.value
     }
+
+    fn get_bounding(&self) -> Bounding {
+        self.bounding
+    }
 }
 
+/// The maps are equal if they have the same content, the keys dropped by the parser are not compared.
+#[derive(Debug, Clone)]
 pub struct FixedUthMapData {
     width: u32,
     height: u32,
     blocks: Vec<BlockInfo>,
+    /// The terrain layer.
     map: Vec<u32>,
+    background: Option<Vec<u32>>,
+    overlay: Option<Vec<u32>>,
+    objects: Vec<MapObject>,
+    /// The keys dropped by the parser as the block already has it.
+    duplicate_keys: Vec<(u32, String)>,
+    /// The keys dropped by the parser as the object already has it.
+    duplicate_object_keys: Vec<(u32, String)>,
+}
+
+impl PartialEq for FixedUthMapData {
+    fn eq(&self, other: &Self) -> bool {
+        self.width == other.width
+            && self.height == other.height
+            && self.blocks == other.blocks
+            && self.map == other.map
+            && self.background == other.background
+            && self.overlay == other.overlay
+            && self.objects == other.objects
+    }
 }
 
 impl BlockInfo {
+    pub fn new(res: String, flags: Vec<String>, value: HashMap<String, f32>, bounding: Bounding) -> Self {
+        Self {
+            res,
+            flags,
+            value,
+            bounding,
+        }
+    }
+
     pub fn get_res(&self) -> &str {
         &self.res
     }
@@ -65,9 +128,32 @@ impl BlockInfo {
     pub fn get_values(&self) -> &HashMap<String, f32> {
         &self.value
     }
+
+    pub fn get_bounding(&self) -> Bounding {
+        self.bounding
+    }
 }
 
 impl FixedUthMapData {
+    /// Create the map data from the block table and the terrain slot grid.
+    /// Return None if the slot count is not `width * height`.
+    pub fn new(width: u32, height: u32, blocks: Vec<BlockInfo>, map: Vec<u32>) -> Option<Self> {
+        if (width as usize).checked_mul(height as usize) != Some(map.len()) {
+            return None;
+        }
+        Some(Self {
+            width,
+            height,
+            blocks,
+            map,
+            background: None,
+            overlay: None,
+            objects: Vec::new(),
+            duplicate_keys: Vec::new(),
+            duplicate_object_keys: Vec::new(),
+        })
+    }
+
     pub fn get_width(&self) -> u32 {
         self.width
     }
@@ -75,90 +161,100 @@ impl FixedUthMapData {
     pub fn get_height(&self) -> u32 {
         self.height
     }
-}
 
-fn read_zero_end_string<'a, 'data>(reader: &'a mut &'data [u8]) -> Result<&'data [u8], &'static str> {
-    reader.iter().position(|x| *x == 0).ok_or("Read zero end string failed").map(|x| {
-        let ret = &reader[0..x];
-        *reader = &reader[x + 1..];
-        ret
-    })
-}
+    pub fn get_blocks(&self) -> &[BlockInfo] {
+        &self.blocks
+    }
 
+    pub fn get_objects(&self) -> &[MapObject] {
+        &self.objects
+    }
 
+    pub fn add_object(&mut self, object: MapObject) {
+        self.objects.push(object);
+    }
 
-impl TryFrom<Vec<u8>> for FixedUthMapData {
-    // todo: ???? str for err. b k s n
-    type Error = &'static str;
+    pub fn remove_object(&mut self, index: usize) -> Option<MapObject> {
+        if index >= self.objects.len() {
+            return None;
+        }
+        let index = index as u32;
+        self.duplicate_object_keys.retain(|(object, _)| *object != index);
+        for (object, _) in &mut self.duplicate_object_keys {
+            if *object > index {
+                *object -= 1;
+            }
+        }
+        Some(self.objects.remove(index as usize))
+    }
 
-    fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
-        let mut reader = &value[..];
+    /// The terrain slots stored row by row from the left top.
+    pub fn get_map(&self) -> &[u32] {
+        &self.map
+    }
 
-        use byteorder::ReadBytesExt;
-        use byteorder::BE;
+    /// Get the slots of the layer, None if the map does not have the layer.
+    /// The terrain layer is always present.
+    pub fn get_layer(&self, layer: Layer) -> Option<&[u32]> {
+        match layer {
+            Layer::Background => self.background.as_deref(),
+            Layer::Terrain => Some(&self.map),
+            Layer::Overlay => self.overlay.as_deref(),
+        }
+    }
 
-        if reader.len() < 3 || &reader[0..3] != b"uth" {
-            return Err("Not map file");
+    /// Replace the slots of the layer.
+    /// Return false and keep the layer if the slot count is not `width * height`.
+    pub fn set_layer(&mut self, layer: Layer, slots: Vec<u32>) -> bool {
+        if slots.len() != self.map.len() {
+            return false;
         }
-        reader = &reader[3..];
-        let blocks = reader.read_u32::<BE>().map_err(|_| "Read blocks failed")?;
-        let width = reader.read_u32::<BE>().map_err(|_| "Read width failed")?;
-        let height = reader.read_u32::<BE>().map_err(|_| "Read height failed")?;
-
-        let mut block_info = Vec::with_capacity(blocks as _);
-
-        for _ in 0..blocks {
-            let flag_count = reader.read_u8().map_err(|_| "Read flag count failed")?;
-            let key_value_count = reader.read_u8().map_err(|_| "Read flag count failed")?;
-            let bounding = reader.read_u8().map_err(|_| "Read flag count failed")?;
-            let res = String::from_utf8(read_zero_end_string(&mut reader)?.into()).map_err(|_| "Read utf8 string failed")?;
-
-            let mut flags = Vec::with_capacity(flag_count as _);
-            for _ in 0..flag_count {
-                let flag = match String::from_utf8(read_zero_end_string(&mut reader)?.into()) {
-                    Ok(s) => s,
-                    Err(_e) => {
-                        return Err("Invalid file, not valid utf8 string");
-                    }
-                };
-                flags.push(flag);
-            }
-            let mut key_values = HashMap::with_capacity(key_value_count as _);
-            for _ in 0..key_value_count {
-                let key = match String::from_utf8(read_zero_end_string(&mut reader)?.into()) {
-                    Ok(s) => s,
-                    Err(_e) => {
-                        return Err("Invalid file, not valid utf8 string");
-                    }
-                };
-                let value = reader.read_f32::<BE>().map_err(|_| "Read f32 be value failed")?;
-                if let Some(_) = key_values.insert(key, value) {
-                    //todo: log dup here.
-                }
-            }
-            block_info.push(BlockInfo {
-                res,
-                flags,
-                value: key_values,
-            })
+        match layer {
+            Layer::Background => self.background = Some(slots),
+            Layer::Terrain => self.map = slots,
+            Layer::Overlay => self.overlay = Some(slots),
         }
-        let mut map = Vec::with_capacity((width * height) as _);
-        for _ in 0..height * width {
-            let block_idx = reader.read_u32::<BE>().map_err(|_| "Read block idx failed")?;
-            map.push(block_idx);
+        true
+    }
+
+    /// Remove the layer and return its slots.
+    /// The terrain layer cannot be removed.
+    pub fn remove_layer(&mut self, layer: Layer) -> Option<Vec<u32>> {
+        match layer {
+            Layer::Background => self.background.take(),
+            Layer::Terrain => None,
+            Layer::Overlay => self.overlay.take(),
         }
+    }
 
-        Ok(Self {
-            width,
-            height,
-            blocks: block_info,
-            map,
-        })
+    /// Get the terrain block index in (x, y) and return None if out of the map.
+    /// The index starts at 1 and 0 is for no block.
+    pub fn get_slot(&self, x: usize, y: usize) -> Option<u32> {
+        self.get_layer_slot(Layer::Terrain, x, y)
+    }
+
+    /// Get the block index of the layer in (x, y).
+    /// Return None if out of the map or the map does not have the layer.
+    pub fn get_layer_slot(&self, layer: Layer, x: usize, y: usize) -> Option<u32> {
+        if x >= self.width as usize || y >= self.height as usize {
+            return None;
+        }
+        self.get_layer(layer)?.get(y * self.width as usize + x).copied()
     }
 }
 
-impl FixedUthMapData {
-    pub fn from_file(mut file: File) -> Result<Self, std::io::Error> {
-        todo!()
+impl GameMap<BlockInfo> for FixedUthMapData {
+    fn get_width(&self) -> Option<NonZeroUsize> {
+        NonZeroUsize::new(self.width as usize)
+    }
+
+    fn get_height(&self) -> Option<NonZeroUsize> {
+        NonZeroUsize::new(self.height as usize)
     }
-}
\ No newline at end of file
+
+    /// Return None for empty slot, out of the map, missing layer or the index not in the block table.
+    fn get_layer_block_info(&self, layer: Layer, x: usize, y: usize) -> Option<&BlockInfo> {
+        let idx = self.get_layer_slot(layer, x, y)?.checked_sub(1)?;
+        self.blocks.get(idx as usize)
+    }
+}
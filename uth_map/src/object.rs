@@ -0,0 +1,41 @@
+use std::collections::HashMap;
+
+/// The free-positioned object in the map such as enemy spawners, player start points, triggers and item drops.
+/// The position is in slots from the left top, so (0.5, 0.5) is the center of the slot (0, 0).
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapObject {
+    kind: String,
+    x: f32,
+    y: f32,
+    flags: Vec<String>,
+    value: HashMap<String, f32>,
+}
+
+impl MapObject {
+    pub fn new(kind: String, x: f32, y: f32, flags: Vec<String>, value: HashMap<String, f32>) -> Self {
+        Self {
+            kind,
+            x,
+            y,
+            flags,
+            value,
+        }
+    }
+
+    /// The type id of the object, what it is decided by the game.
+    pub fn get_kind(&self) -> &str {
+        &self.kind
+    }
+
+    pub fn get_pos(&self) -> (f32, f32) {
+        (self.x, self.y)
+    }
+
+    pub fn get_flags(&self) -> &[String] {
+        &self.flags
+    }
+
+    pub fn get_values(&self) -> &HashMap<String, f32> {
+        &self.value
+    }
+}
@@ -0,0 +1,129 @@
+//! The grid pathfinding respecting the block bounding.
+//!
+//! The bounding blocks the movement towards its directions, both leaving and entering the slot.
+//! So the `UpPass` slot can only be passed upward and the `AllBlock` slot is a wall.
+//! The empty slots are free to pass.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use crate::bound::{Bounding, Direction};
+use crate::{GameBlock, GameMap};
+
+/// Get the slot next to (x, y) in the direction, None if out of the map.
+pub fn neighbour(width: usize, height: usize, (x, y): (usize, usize), to: Direction) -> Option<(usize, usize)> {
+    let (dx, dy) = to.offset();
+    let x = x.checked_add_signed(dx)?;
+    let y = y.checked_add_signed(dy)?;
+    (x < width && y < height).then_some((x, y))
+}
+
+/// Check whether moving from the slot to the neighbour in the direction is not bounded.
+pub fn can_move(bounding: impl Fn(usize, usize) -> Bounding, width: usize, height: usize, from: (usize, usize), to: Direction) -> Option<(usize, usize)> {
+    let next = neighbour(width, height, from, to)?;
+    if bounding(from.0, from.1).check_bound(to) || bounding(next.0, next.1).check_bound(to) {
+        return None;
+    }
+    Some(next)
+}
+
+/// Find the shortest path with A* in 4 directions.
+/// The path contains both `from` and `to`, None if no path or out of the map.
+pub fn find_path_by(bounding: impl Fn(usize, usize) -> Bounding, width: usize, height: usize, from: (usize, usize), to: (usize, usize)) -> Option<Vec<(usize, usize)>> {
+    if from.0 >= width || from.1 >= height || to.0 >= width || to.1 >= height {
+        return None;
+    }
+    let idx = |(x, y): (usize, usize)| y * width + x;
+    let heuristic = |(x, y): (usize, usize)| x.abs_diff(to.0) + y.abs_diff(to.1);
+
+    let mut came_from = vec![usize::MAX; width * height];
+    let mut cost = vec![usize::MAX; width * height];
+    let mut open = BinaryHeap::new();
+    cost[idx(from)] = 0;
+    open.push(Reverse((heuristic(from), 0, from)));
+
+    while let Some(Reverse((_, current_cost, current))) = open.pop() {
+        if current == to {
+            let mut path = vec![current];
+            let mut i = idx(current);
+            while i != idx(from) {
+                i = came_from[i];
+                path.push((i % width, i / width));
+            }
+            path.reverse();
+            return Some(path);
+        }
+        if current_cost > cost[idx(current)] {
+            continue;
+        }
+        for dir in Direction::ALL {
+            if let Some(next) = can_move(&bounding, width, height, current, dir) {
+                let next_cost = current_cost + 1;
+                if next_cost < cost[idx(next)] {
+                    cost[idx(next)] = next_cost;
+                    came_from[idx(next)] = idx(current);
+                    open.push(Reverse((next_cost + heuristic(next), next_cost, next)));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Find the shortest path in the terrain of the map by the block bounding.
+pub fn find_path<Block: GameBlock, M: GameMap<Block>>(map: &M, from: (usize, usize), to: (usize, usize)) -> Option<Vec<(usize, usize)>> {
+    let width = map.get_width()?.get();
+    let height = map.get_height()?.get();
+    find_path_by(|x, y| map.get_block_info(x, y).map(|b| b.get_bounding()).unwrap_or(Bounding::No), width, height, from, to)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The rows of the bounding, one char per slot: `.` no, `#` all block, `^` up pass.
+    fn grid<'a>(rows: &'a [&'a str]) -> (impl Fn(usize, usize) -> Bounding + 'a, usize, usize) {
+        let bounding = move |x: usize, y: usize| match rows[y].as_bytes()[x] {
+            b'#' => Bounding::AllBlock,
+            b'^' => Bounding::UpPass,
+            _ => Bounding::No,
+        };
+        (bounding, rows[0].len(), rows.len())
+    }
+
+    #[test]
+    fn up_pass_one_way() {
+        let (bounding, width, height) = grid(&[
+            "...",
+            "#^#",
+            "#^#",
+            "#^#",
+            "...",
+        ]);
+        let up = find_path_by(&bounding, width, height, (1, 4), (1, 0)).unwrap();
+        assert_eq!(up, vec![(1, 4), (1, 3), (1, 2), (1, 1), (1, 0)]);
+        assert_eq!(find_path_by(&bounding, width, height, (1, 0), (1, 4)), None);
+    }
+
+    #[test]
+    fn detour_around_wall() {
+        let (bounding, width, height) = grid(&[
+            "....",
+            ".##.",
+            ".#..",
+            ".#..",
+        ]);
+        let path = find_path_by(&bounding, width, height, (0, 3), (2, 3)).unwrap();
+        assert_eq!(path.len(), 11);
+        assert!(path.iter().all(|&(x, y)| bounding(x, y) == Bounding::No));
+        assert!(path.windows(2).all(|w| w[0].0.abs_diff(w[1].0) + w[0].1.abs_diff(w[1].1) == 1));
+    }
+
+    #[test]
+    fn out_of_map() {
+        let (bounding, width, height) = grid(&["...", "..."]);
+        assert_eq!(find_path_by(&bounding, width, height, (3, 0), (0, 0)), None);
+        assert_eq!(find_path_by(&bounding, width, height, (0, 0), (0, 2)), None);
+        assert_eq!(find_path_by(&bounding, width, height, (0, 0), (0, 0)), Some(vec![(0, 0)]));
+    }
+}
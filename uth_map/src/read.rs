@@ -0,0 +1,138 @@
+use std::io::{BufRead, Read};
+
+use byteorder::{ReadBytesExt, BE};
+
+use crate::error::{MapField, MapParseError};
+
+/// The most items allocated ahead from a count in the file.
+/// More items grow the storage while they are read, so a small broken file cannot allocate much.
+pub(crate) const PREALLOCATE_LIMIT: usize = 1 << 16;
+
+/// The reader that tracks the byte offset for error reporting.
+pub(crate) struct MapReader<R: BufRead> {
+    inner: R,
+    offset: u64,
+}
+
+impl<R: BufRead> MapReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            offset: 0,
+        }
+    }
+
+    /// Start from `offset` bytes of the file, for the reader seeked into the file.
+    pub fn with_offset(inner: R, offset: u64) -> Self {
+        Self {
+            inner,
+            offset,
+        }
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    fn io_err(&self, field: MapField, offset: u64, source: std::io::Error) -> MapParseError {
+        MapParseError::Io {
+            field,
+            offset,
+            source,
+        }
+    }
+
+    pub fn read_exact(&mut self, buf: &mut [u8], field: MapField) -> Result<(), MapParseError> {
+        let offset = self.offset;
+        self.inner.read_exact(buf).map_err(|e| self.io_err(field, offset, e))?;
+        self.offset += buf.len() as u64;
+        Ok(())
+    }
+
+    pub fn read_u8(&mut self, field: MapField) -> Result<u8, MapParseError> {
+        let offset = self.offset;
+        let v = self.inner.read_u8().map_err(|e| self.io_err(field, offset, e))?;
+        self.offset += 1;
+        Ok(v)
+    }
+
+    /// Look at the next byte without consuming it.
+    pub fn peek_u8(&mut self, field: MapField) -> Result<u8, MapParseError> {
+        let offset = self.offset;
+        match self.inner.fill_buf() {
+            Ok(buf) => match buf.first() {
+                Some(b) => Ok(*b),
+                None => Err(self.io_err(field, offset, std::io::ErrorKind::UnexpectedEof.into())),
+            },
+            Err(e) => Err(self.io_err(field, offset, e)),
+        }
+    }
+
+    pub fn read_u16(&mut self, field: MapField) -> Result<u16, MapParseError> {
+        let offset = self.offset;
+        let v = self.inner.read_u16::<BE>().map_err(|e| self.io_err(field, offset, e))?;
+        self.offset += 2;
+        Ok(v)
+    }
+
+    pub fn read_u32(&mut self, field: MapField) -> Result<u32, MapParseError> {
+        let offset = self.offset;
+        let v = self.inner.read_u32::<BE>().map_err(|e| self.io_err(field, offset, e))?;
+        self.offset += 4;
+        Ok(v)
+    }
+
+    pub fn read_u64(&mut self, field: MapField) -> Result<u64, MapParseError> {
+        let offset = self.offset;
+        let v = self.inner.read_u64::<BE>().map_err(|e| self.io_err(field, offset, e))?;
+        self.offset += 8;
+        Ok(v)
+    }
+
+    /// Read the LEB128 varint of at most 5 bytes.
+    pub fn read_varint(&mut self, field: MapField) -> Result<u32, MapParseError> {
+        let offset = self.offset;
+        let mut value = 0u32;
+        for shift in (0..35).step_by(7) {
+            let byte = self.read_u8(field)?;
+            let bits = (byte & 0x7F) as u32;
+            if shift == 28 && bits > 0x0F {
+                return Err(MapParseError::InvalidVarint { field, offset });
+            }
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(MapParseError::InvalidVarint { field, offset })
+    }
+
+    pub fn read_f32(&mut self, field: MapField) -> Result<f32, MapParseError> {
+        let offset = self.offset;
+        let v = self.inner.read_f32::<BE>().map_err(|e| self.io_err(field, offset, e))?;
+        self.offset += 4;
+        Ok(v)
+    }
+
+    /// Skip `len` bytes, fail if the reader ends before.
+    pub fn skip(&mut self, len: u64, field: MapField) -> Result<(), MapParseError> {
+        let offset = self.offset;
+        let skipped = std::io::copy(&mut (&mut self.inner).take(len), &mut std::io::sink()).map_err(|e| self.io_err(field, offset, e))?;
+        self.offset += skipped;
+        if skipped != len {
+            return Err(self.io_err(field, offset, std::io::ErrorKind::UnexpectedEof.into()));
+        }
+        Ok(())
+    }
+
+    pub fn read_zero_end_string(&mut self, field: MapField) -> Result<String, MapParseError> {
+        let offset = self.offset;
+        let mut buf = Vec::new();
+        let len = self.inner.read_until(0, &mut buf).map_err(|e| self.io_err(field, offset, e))?;
+        self.offset += len as u64;
+        if buf.pop() != Some(0) {
+            return Err(self.io_err(field, offset, std::io::ErrorKind::UnexpectedEof.into()));
+        }
+        String::from_utf8(buf).map_err(|_| MapParseError::InvalidUtf8 { field, offset })
+    }
+}
@@ -0,0 +1,407 @@
+//! The plain-text form of the uth map for reviewing in diffs.
+//!
+//! ```text
+//! uth 1
+//! size 3 2
+//! block "grass"
+//!     bounding No
+//!     flag "solid"
+//!     value "hp" 3.5
+//! end
+//! layer terrain
+//! 1 0 1
+//! 0 0 1
+//! object "spawner" 1.5 0.5
+//!     value "hp" 10.0
+//! end
+//! ```
+//!
+//! The blocks are numbered from 1 in order.
+//! Every layer has `height` rows of `width` block indices, or no row if the width is 0. The terrain is all 0 if absent.
+//! The strings are quoted with the rust escapes and `#` starts the comment line.
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter, Write};
+
+use crate::bound::Bounding;
+use crate::read::PREALLOCATE_LIMIT;
+use crate::{BlockInfo, FixedUthMapData, Layer, MapObject};
+
+/// The version of the text form.
+const TEXT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextParseError {
+    /// The line number start at 1.
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for TextParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for TextParseError {}
+
+pub(crate) fn layer_name(layer: Layer) -> &'static str {
+    match layer {
+        Layer::Background => "background",
+        Layer::Terrain => "terrain",
+        Layer::Overlay => "overlay",
+    }
+}
+
+/// Write the slots in rows of `width`, nothing for the zero width as the rows would be empty lines.
+pub(crate) fn write_rows(out: &mut String, slots: &[u32], width: u32) {
+    if width == 0 {
+        return;
+    }
+    for row in slots.chunks(width as usize) {
+        let row = row.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+        out.push_str(&row.join(" "));
+        out.push('\n');
+    }
+}
+
+pub(crate) fn write_properties(out: &mut String, flags: &[String], values: &HashMap<String, f32>) {
+    for flag in flags {
+        let _ = writeln!(out, "    flag {:?}", flag);
+    }
+    let mut key_values = values.iter().collect::<Vec<_>>();
+    key_values.sort_unstable_by(|a, b| a.0.cmp(b.0));
+    for (key, value) in key_values {
+        let _ = writeln!(out, "    value {:?} {:?}", key, value);
+    }
+}
+
+impl FixedUthMapData {
+    /// Write the map in the text form.
+    /// The same map always produces the same text.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "uth {}", TEXT_VERSION);
+        let _ = writeln!(out, "size {} {}", self.width, self.height);
+        for block in &self.blocks {
+            let _ = writeln!(out, "block {:?}", block.res);
+            let _ = writeln!(out, "    bounding {:?}", block.bounding);
+            write_properties(&mut out, &block.flags, &block.value);
+            out.push_str("end\n");
+        }
+        for layer in Layer::ALL {
+            let slots = match self.get_layer(layer) {
+                Some(slots) => slots,
+                None => continue,
+            };
+            let _ = writeln!(out, "layer {}", layer_name(layer));
+            write_rows(&mut out, slots, self.width);
+        }
+        for object in &self.objects {
+            let (x, y) = object.get_pos();
+            let _ = writeln!(out, "object {:?} {:?} {:?}", object.get_kind(), x, y);
+            write_properties(&mut out, object.get_flags(), object.get_values());
+            out.push_str("end\n");
+        }
+        out
+    }
+
+    /// Parse the map from the text form.
+    pub fn from_text(text: &str) -> Result<Self, TextParseError> {
+        TextParser::new(text).parse()
+    }
+}
+
+/// Split the line into words and quoted strings.
+fn tokenize(line: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            let mut s = String::new();
+            loop {
+                match chars.next() {
+                    None => return Err("Unterminated string".into()),
+                    Some('"') => break,
+                    Some('\\') => s.push(match chars.next() {
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('0') => '\0',
+                        Some('\\') => '\\',
+                        Some('"') => '"',
+                        Some('\'') => '\'',
+                        Some('u') => {
+                            if chars.next() != Some('{') {
+                                return Err("Invalid unicode escape".into());
+                            }
+                            let mut hex = String::new();
+                            for c in chars.by_ref() {
+                                if c == '}' {
+                                    break;
+                                }
+                                hex.push(c);
+                            }
+                            u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32).ok_or("Invalid unicode escape")?
+                        }
+                        _ => return Err("Invalid escape".into()),
+                    }),
+                    Some(c) => s.push(c),
+                }
+            }
+            tokens.push(s);
+        } else {
+            let mut s = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                s.push(c);
+                chars.next();
+            }
+            tokens.push(s);
+        }
+    }
+    Ok(tokens)
+}
+
+/// The content between the block or object line and `end`.
+pub(crate) struct Body {
+    pub bounding: Option<Bounding>,
+    pub flags: Vec<String>,
+    pub value: HashMap<String, f32>,
+    pub duplicate_keys: Vec<String>,
+}
+
+pub(crate) fn layer_by_name(name: &str) -> Option<Layer> {
+    Layer::ALL.into_iter().find(|x| layer_name(*x) == name)
+}
+
+pub(crate) fn bounding_by_name(name: &str) -> Option<Bounding> {
+    (0..16).filter_map(|x| Bounding::try_from(x).ok()).find(|b| format!("{:?}", b) == name)
+}
+
+pub(crate) struct TextParser<'a> {
+    lines: std::iter::Enumerate<std::str::Lines<'a>>,
+    line: usize,
+}
+
+impl<'a> TextParser<'a> {
+    pub fn new(text: &'a str) -> Self {
+        Self {
+            lines: text.lines().enumerate(),
+            line: 0,
+        }
+    }
+
+    pub fn err<T>(&self, message: impl Into<String>) -> Result<T, TextParseError> {
+        Err(TextParseError { line: self.line, message: message.into() })
+    }
+
+    /// Get the tokens of the next line which is not empty or comment.
+    pub fn next_tokens(&mut self) -> Result<Option<Vec<String>>, TextParseError> {
+        for (line, content) in self.lines.by_ref() {
+            self.line = line + 1;
+            let content = content.trim();
+            if content.is_empty() || content.starts_with('#') {
+                continue;
+            }
+            return match tokenize(content) {
+                Ok(tokens) => Ok(Some(tokens)),
+                Err(e) => self.err(e),
+            };
+        }
+        Ok(None)
+    }
+
+    pub fn expect_tokens(&mut self, what: &str) -> Result<Vec<String>, TextParseError> {
+        match self.next_tokens()? {
+            Some(tokens) => Ok(tokens),
+            None => self.err(format!("Unexpected end, expect {}", what)),
+        }
+    }
+
+    pub fn number<T: std::str::FromStr>(&self, token: &str) -> Result<T, TextParseError> {
+        match token.parse() {
+            Ok(v) => Ok(v),
+            Err(_) => self.err(format!("Invalid number {:?}", token)),
+        }
+    }
+
+    pub fn args<'t>(&self, tokens: &'t [String], count: usize) -> Result<&'t [String], TextParseError> {
+        if tokens.len() != count + 1 {
+            return self.err(format!("{} expects {} arguments", tokens[0], count));
+        }
+        Ok(&tokens[1..])
+    }
+
+    /// Parse `height` rows of `width` slots, no row for the zero width, see [`write_rows`].
+    pub fn parse_rows(&mut self, width: u32, height: u32) -> Result<Vec<u32>, TextParseError> {
+        if width == 0 {
+            return Ok(Vec::new());
+        }
+        let mut slots = Vec::with_capacity((width as usize).saturating_mul(height as usize).min(PREALLOCATE_LIMIT));
+        for _ in 0..height {
+            let row = self.expect_tokens("layer row")?;
+            if row.len() != width as usize {
+                return self.err(format!("Layer row expects {} slots", width));
+            }
+            for slot in &row {
+                slots.push(self.number(slot)?);
+            }
+        }
+        Ok(slots)
+    }
+
+    /// Parse the lines until `end`.
+    pub fn parse_body(&mut self, allow_bounding: bool) -> Result<Body, TextParseError> {
+        let mut body = Body {
+            bounding: None,
+            flags: Vec::new(),
+            value: HashMap::new(),
+            duplicate_keys: Vec::new(),
+        };
+        loop {
+            let tokens = self.expect_tokens("end")?;
+            match tokens[0].as_str() {
+                "end" => {
+                    self.args(&tokens, 0)?;
+                    return Ok(body);
+                }
+                "bounding" if allow_bounding => {
+                    let name = &self.args(&tokens, 1)?[0];
+                    body.bounding = Some(match bounding_by_name(name) {
+                        Some(b) => b,
+                        None => return self.err(format!("Unknown bounding {:?}", name)),
+                    });
+                }
+                "flag" => body.flags.push(self.args(&tokens, 1)?[0].clone()),
+                "value" => {
+                    let args = self.args(&tokens, 2)?;
+                    let v = self.number(&args[1])?;
+                    if body.value.insert(args[0].clone(), v).is_some() {
+                        body.duplicate_keys.push(args[0].clone());
+                    }
+                }
+                other => return self.err(format!("Unknown property {:?}", other)),
+            }
+        }
+    }
+
+    fn parse(mut self) -> Result<FixedUthMapData, TextParseError> {
+        let tokens = self.expect_tokens("header")?;
+        if tokens[0] != "uth" {
+            return self.err("Not uth map text");
+        }
+        let version: u32 = self.number(&self.args(&tokens, 1)?[0])?;
+        if version != TEXT_VERSION {
+            return self.err(format!("Unsupported text version {}", version));
+        }
+        let tokens = self.expect_tokens("size")?;
+        if tokens[0] != "size" {
+            return self.err("Expect size");
+        }
+        let args = self.args(&tokens, 2)?;
+        let (width, height): (u32, u32) = (self.number(&args[0])?, self.number(&args[1])?);
+        let size_line = self.line;
+        let size_err = || Err(TextParseError { line: size_line, message: "Map size overflow".into() });
+        let slots = match (width as usize).checked_mul(height as usize) {
+            Some(slots) => slots,
+            None => return size_err(),
+        };
+        let mut blocks = Vec::new();
+        let mut layers: [Option<Vec<u32>>; 3] = [None, None, None];
+        let mut objects = Vec::new();
+        let mut duplicate_keys = Vec::new();
+        let mut duplicate_object_keys = Vec::new();
+
+        while let Some(tokens) = self.next_tokens()? {
+            match tokens[0].as_str() {
+                "block" => {
+                    let res = self.args(&tokens, 1)?[0].clone();
+                    let block = blocks.len() as u32;
+                    let body = self.parse_body(true)?;
+                    duplicate_keys.extend(body.duplicate_keys.into_iter().map(|key| (block, key)));
+                    blocks.push(BlockInfo::new(res, body.flags, body.value, body.bounding.unwrap_or(Bounding::No)));
+                }
+                "layer" => {
+                    let name = &self.args(&tokens, 1)?[0];
+                    let layer = match layer_by_name(name) {
+                        Some(layer) => layer,
+                        None => return self.err(format!("Unknown layer {:?}", name)),
+                    };
+                    layers[layer as usize] = Some(self.parse_rows(width, height)?);
+                }
+                "object" => {
+                    let args = self.args(&tokens, 3)?;
+                    let (kind, x, y) = (args[0].clone(), self.number(&args[1])?, self.number(&args[2])?);
+                    let object = objects.len() as u32;
+                    let body = self.parse_body(false)?;
+                    duplicate_object_keys.extend(body.duplicate_keys.into_iter().map(|key| (object, key)));
+                    objects.push(MapObject::new(kind, x, y, body.flags, body.value));
+                }
+                other => return self.err(format!("Unknown entry {:?}", other)),
+            }
+        }
+
+        // the absent terrain is all empty, which may be too large for the memory.
+        let terrain = match layers[Layer::Terrain as usize].take() {
+            Some(slots) => slots,
+            None => {
+                let mut terrain = Vec::new();
+                if terrain.try_reserve_exact(slots).is_err() {
+                    return size_err();
+                }
+                terrain.resize(slots, 0);
+                terrain
+            }
+        };
+        let mut map = match FixedUthMapData::new(width, height, blocks, terrain) {
+            Some(map) => map,
+            None => return size_err(),
+        };
+        for layer in [Layer::Background, Layer::Overlay] {
+            if let Some(slots) = layers[layer as usize].take() {
+                map.set_layer(layer, slots);
+            }
+        }
+        map.objects = objects;
+        map.duplicate_keys = duplicate_keys;
+        map.duplicate_object_keys = duplicate_object_keys;
+        Ok(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut value = HashMap::new();
+        value.insert("hp".to_string(), 3.5);
+        let blocks = vec![BlockInfo::new("say \"hi\"\n".into(), vec!["solid".into()], value.clone(), Bounding::LeftDown)];
+        let mut map = FixedUthMapData::new(3, 2, blocks, vec![1, 0, 1, 0, 0, 1]).unwrap();
+        map.set_layer(Layer::Overlay, vec![0, 1, 0, 0, 0, 0]);
+        map.objects.push(MapObject::new("spawner".into(), 1.5, 0.5, Vec::new(), value));
+        let text = map.to_text();
+        let read = FixedUthMapData::from_text(&text).unwrap();
+        assert_eq!(read, map);
+        assert_eq!(read.to_text(), text);
+    }
+
+    #[test]
+    fn zero_width() {
+        let map = FixedUthMapData::new(0, 2, Vec::new(), Vec::new()).unwrap();
+        assert_eq!(FixedUthMapData::from_text(&map.to_text()), Ok(map));
+    }
+
+    #[test]
+    fn huge_size_without_terrain() {
+        let err = FixedUthMapData::from_text("uth 1\nsize 4294967295 4294967295\n").unwrap_err();
+        assert_eq!(err.line, 2);
+    }
+}
@@ -0,0 +1,200 @@
+use std::fmt::{Display, Formatter};
+
+use crate::{FixedUthMapData, Layer};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Severity {
+    /// The map can be loaded but maybe not as the author expected.
+    Warning,
+    /// The map should not be shipped.
+    Error,
+}
+
+/// The problem found in the map.
+/// The block is the index in the block table (start at 0).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MapIssue {
+    ZeroDimension { width: u32, height: u32 },
+    /// The slot refers to a block not in the block table.
+    DanglingIndex { layer: Layer, x: u32, y: u32, index: u32 },
+    /// The key appears more than once in the block, only the last value is kept.
+    DuplicateKey { block: u32, key: String },
+    /// The block is not used by any slot.
+    UnusedBlock { block: u32 },
+    EmptyResource { block: u32 },
+    DuplicateObjectKey { object: u32, key: String },
+    EmptyObjectKind { object: u32 },
+}
+
+impl MapIssue {
+    pub fn severity(&self) -> Severity {
+        match self {
+            MapIssue::DuplicateKey { .. } | MapIssue::UnusedBlock { .. } | MapIssue::DuplicateObjectKey { .. } => Severity::Warning,
+            MapIssue::ZeroDimension { .. } | MapIssue::DanglingIndex { .. } | MapIssue::EmptyResource { .. } | MapIssue::EmptyObjectKind { .. } => Severity::Error,
+        }
+    }
+}
+
+impl Display for MapIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MapIssue::ZeroDimension { width, height } => write!(f, "Map size {}x{} has zero dimension", width, height),
+            MapIssue::DanglingIndex { layer, x, y, index } => write!(f, "Slot ({}, {}) of layer {:?} refers to block {} which is not in the block table", x, y, layer, index),
+            MapIssue::DuplicateKey { block, key } => write!(f, "Duplicate key {:?} in block {}", key, block),
+            MapIssue::UnusedBlock { block } => write!(f, "Block {} is not used", block),
+            MapIssue::EmptyResource { block } => write!(f, "Block {} has empty resource id", block),
+            MapIssue::DuplicateObjectKey { object, key } => write!(f, "Duplicate key {:?} in object {}", key, object),
+            MapIssue::EmptyObjectKind { object } => write!(f, "Object {} has empty type id", object),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ValidationReport {
+    pub issues: Vec<MapIssue>,
+}
+
+impl ValidationReport {
+    pub fn has_errors(&self) -> bool {
+        self.errors().next().is_some()
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item=&MapIssue> {
+        self.issues.iter().filter(|x| x.severity() == Severity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item=&MapIssue> {
+        self.issues.iter().filter(|x| x.severity() == Severity::Warning)
+    }
+}
+
+impl FixedUthMapData {
+    /// Check the map for problems the parser accepts.
+    pub fn validate(&self) -> ValidationReport {
+        let mut issues = Vec::new();
+        if self.width == 0 || self.height == 0 {
+            issues.push(MapIssue::ZeroDimension { width: self.width, height: self.height });
+        }
+
+        let mut used = vec![false; self.blocks.len()];
+        for layer in Layer::ALL {
+            let slots = match self.get_layer(layer) {
+                Some(slots) => slots,
+                None => continue,
+            };
+            for (i, &index) in slots.iter().enumerate() {
+                if index == 0 {
+                    continue;
+                }
+                match used.get_mut(index as usize - 1) {
+                    Some(used) => *used = true,
+                    None => {
+                        let width = self.width as usize;
+                        issues.push(MapIssue::DanglingIndex { layer, x: (i % width) as u32, y: (i / width) as u32, index });
+                    }
+                }
+            }
+        }
+
+        for (block, key) in &self.duplicate_keys {
+            issues.push(MapIssue::DuplicateKey { block: *block, key: key.clone() });
+        }
+        for (block, info) in self.blocks.iter().enumerate() {
+            let block = block as u32;
+            if info.res.is_empty() {
+                issues.push(MapIssue::EmptyResource { block });
+            }
+            if !used[block as usize] {
+                issues.push(MapIssue::UnusedBlock { block });
+            }
+        }
+        for (object, key) in &self.duplicate_object_keys {
+            issues.push(MapIssue::DuplicateObjectKey { object: *object, key: key.clone() });
+        }
+        for (object, info) in self.objects.iter().enumerate() {
+            if info.get_kind().is_empty() {
+                issues.push(MapIssue::EmptyObjectKind { object: object as u32 });
+            }
+        }
+        ValidationReport { issues }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = r#"uth 1
+size 3 2
+block ""
+    bounding No
+end
+block "stone"
+    bounding AllBlock
+    value "hp" 1
+    value "hp" 2
+end
+layer terrain
+1 0 0
+0 3 1
+object "" 0 0
+end
+object "spawner" 1 1
+    value "wave" 1
+    value "wave" 2
+end
+"#;
+
+    #[test]
+    fn every_issue() {
+        let map = FixedUthMapData::from_text(TEXT).unwrap();
+        let report = map.validate();
+        assert_eq!(report.issues, vec![
+            MapIssue::DanglingIndex { layer: Layer::Terrain, x: 1, y: 1, index: 3 },
+            MapIssue::DuplicateKey { block: 1, key: "hp".into() },
+            MapIssue::EmptyResource { block: 0 },
+            MapIssue::UnusedBlock { block: 1 },
+            MapIssue::DuplicateObjectKey { object: 1, key: "wave".into() },
+            MapIssue::EmptyObjectKind { object: 0 },
+        ]);
+        assert!(report.has_errors());
+        assert_eq!(report.errors().count(), 3);
+        assert_eq!(report.warnings().count(), 3);
+
+        let empty = FixedUthMapData::new(0, 3, Vec::new(), Vec::new()).unwrap().validate();
+        assert_eq!(empty.issues, vec![MapIssue::ZeroDimension { width: 0, height: 3 }]);
+        assert!(empty.has_errors());
+    }
+
+    #[test]
+    fn warnings_only() {
+        let text = TEXT.replace("block \"\"", "block \"grass\"").replace("0 3 1", "0 0 1").replace("object \"\"", "object \"light\"");
+        let report = FixedUthMapData::from_text(&text).unwrap().validate();
+        assert_eq!(report.warnings().count(), 3);
+        assert!(!report.has_errors());
+    }
+
+    #[test]
+    fn dropped_keys_not_compared() {
+        let map = FixedUthMapData::from_text(TEXT).unwrap();
+        let read = FixedUthMapData::from_text(&map.to_text()).unwrap();
+        assert!(read.validate().issues.len() < map.validate().issues.len());
+        assert_eq!(read, map);
+    }
+
+    #[test]
+    fn dropped_keys_follow_removal() {
+        let mut map = FixedUthMapData::from_text(TEXT).unwrap();
+        map.remove_object(0);
+        assert!(map.validate().issues.contains(&MapIssue::DuplicateObjectKey { object: 0, key: "wave".into() }));
+        map.remove_object(0);
+        assert!(!map.validate().issues.iter().any(|x| matches!(x, MapIssue::DuplicateObjectKey { .. })));
+
+        let stone = "block \"stone\"\n    bounding AllBlock\n    value \"hp\" 1\n    value \"hp\" 2\nend\n";
+        assert!(TEXT.contains(stone));
+        let without_stone = FixedUthMapData::from_text(&TEXT.replace(stone, "")).unwrap();
+        let mut map = FixedUthMapData::from_text(TEXT).unwrap();
+        map.diff(&without_stone).unwrap().apply(&mut map).unwrap();
+        assert!(!map.validate().issues.iter().any(|x| matches!(x, MapIssue::DuplicateKey { .. })));
+    }
+}